@@ -0,0 +1,57 @@
+//! Loads arbitrary bytes as a ROM and runs it. Any panic is a bug, a bad
+//! ROM should only ever produce a `CoreError` or `LoadError`.
+//!
+//! The first byte picks the machine and quirks, the second a key that is
+//! toggled every frame so keypad code gets exercised, the rest is the ROM.
+
+#![no_main]
+#[macro_use] extern crate libfuzzer_sys;
+extern crate chip8r;
+
+use chip8r::chip8::{Core, Machine};
+use chip8r::quirks::{Preset, Quirks};
+
+const CYCLES: u32 = 10_000;
+const CYCLES_PER_FRAME: u32 = 14;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+
+    let machine = match data[0] % 3 {
+        0 => Machine::Chip8,
+        1 => Machine::SuperChip,
+        _ => Machine::XoChip,
+    };
+    let preset = match data[0] / 3 % 4 {
+        0 => Preset::CosmacVip,
+        1 => Preset::Chip48,
+        2 => Preset::SuperChip11,
+        _ => Preset::Octo,
+    };
+    let key = (data[1] & 0xf) as usize;
+
+    let mut core = Core::new(machine, Quirks::from_preset(preset));
+    if core.load_rom(&data[2..]).is_err() {
+        return;
+    }
+
+    for cycle in 0..CYCLES {
+        if cycle % CYCLES_PER_FRAME == 0 {
+            core.tick_timers();
+            if cycle / CYCLES_PER_FRAME % 2 == 0 {
+                core.set_key(key);
+            } else {
+                core.clear_key(key);
+            }
+        }
+        if core.tick().is_err() {
+            break;
+        }
+    }
+
+    // Whatever state the ROM left behind has to survive a snapshot
+    let state = core.save_state();
+    core.load_state(&state).unwrap();
+});
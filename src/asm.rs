@@ -0,0 +1,600 @@
+use disasm::Instruction;
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+/// Address programs are assembled to run from
+pub const ORIGIN: usize = 0x200;
+
+/// An assembly error, located by 1-based line and column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl error::Error for AsmError {}
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    col: usize,
+}
+
+// A value built from numbers, constants and labels joined with + and -
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(bool, Token)>,
+    col: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(u8),
+    // I, DT, ST, K, F, HF, B, R and [I]
+    Keyword(String),
+    Long(Expr),
+    Value(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct Arg {
+    operand: Operand,
+    col: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Item {
+    Inst(Token, Vec<Arg>),
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+    Raw(Vec<u8>),
+}
+
+struct Line {
+    line: usize,
+    addr: usize,
+    item: Item,
+}
+
+const KEYWORDS: [&str; 9] = ["I", "DT", "ST", "K", "F", "HF", "B", "R", "[I]"];
+
+fn err<T>(line: usize, column: usize, message: String) -> Result<T, AsmError> {
+    Err(AsmError { line, column, message })
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut cur = String::new();
+    let mut start = 0;
+
+    for (idx, c) in text.chars().enumerate() {
+        let col = idx + 1;
+        if c == ';' {
+            break;
+        }
+
+        if c.is_whitespace() || c == ',' || c == '+' || c == '-' {
+            if !cur.is_empty() {
+                tokens.push(Token { text: cur.clone(), col: start });
+                cur.clear();
+            }
+            if !c.is_whitespace() {
+                tokens.push(Token { text: c.to_string(), col });
+            }
+        } else {
+            if cur.is_empty() {
+                start = col;
+            }
+            cur.push(c);
+        }
+    }
+
+    if !cur.is_empty() {
+        tokens.push(Token { text: cur, col: start });
+    }
+    tokens
+}
+
+fn is_ident(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(c) if c.is_alphabetic() || c == '_' => {},
+        _ => return false,
+    }
+    chars.all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let upper = text.to_uppercase();
+    if upper.len() == 2 && upper.starts_with('V') {
+        u8::from_str_radix(&upper[1..], 16).ok()
+    } else {
+        None
+    }
+}
+
+fn is_reserved(text: &str) -> bool {
+    let upper = text.to_uppercase();
+    parse_register(text).is_some() || KEYWORDS.contains(&upper.as_str()) || upper == "LONG"
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let lower = text.to_lowercase();
+    if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()
+    } else if lower.chars().all(|c| c.is_ascii_digit()) {
+        lower.parse().ok()
+    } else {
+        None
+    }
+}
+
+// Split tokens on commas
+fn split_args(line: usize, tokens: &[Token]) -> Result<Vec<Vec<Token>>, AsmError> {
+    let mut args = Vec::new();
+    let mut cur: Vec<Token> = Vec::new();
+
+    for token in tokens {
+        if token.text == "," {
+            if cur.is_empty() {
+                return err(line, token.col, "missing operand before ','".to_string());
+            }
+            args.push(cur);
+            cur = Vec::new();
+        } else {
+            cur.push(token.clone());
+        }
+    }
+
+    if let Some(last) = tokens.last() {
+        if cur.is_empty() {
+            return err(line, last.col, "missing operand after ','".to_string());
+        }
+        args.push(cur);
+    }
+    Ok(args)
+}
+
+fn parse_expr(line: usize, tokens: &[Token]) -> Result<Expr, AsmError> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut expect_term = true;
+
+    for token in tokens {
+        match (expect_term, token.text.as_str()) {
+            (true, "-") => negative = !negative,
+            (true, "+") => {},
+            (true, _) => {
+                if parse_number(&token.text).is_none()
+                    && (!is_ident(&token.text) || is_reserved(&token.text))
+                {
+                    return err(line, token.col, format!("invalid value '{}'", token.text));
+                }
+                terms.push((negative, token.clone()));
+                negative = false;
+                expect_term = false;
+            },
+            (false, "+") | (false, "-") => {
+                negative = token.text == "-";
+                expect_term = true;
+            },
+            (false, _) => {
+                return err(line, token.col, format!("expected '+' or '-' before '{}'", token.text));
+            },
+        }
+    }
+
+    match (tokens.first(), expect_term) {
+        (Some(first), false) => Ok(Expr { terms, col: first.col }),
+        (Some(_), true) => {
+            let last = &tokens[tokens.len() - 1];
+            err(line, last.col, "expression ends with an operator".to_string())
+        },
+        (None, _) => err(line, 1, "missing value".to_string()),
+    }
+}
+
+fn parse_arg(line: usize, tokens: &[Token]) -> Result<Arg, AsmError> {
+    let col = tokens[0].col;
+    let first = tokens[0].text.to_uppercase();
+
+    let operand = if tokens.len() == 1 && parse_register(&first).is_some() {
+        Operand::Reg(parse_register(&first).unwrap())
+    } else if tokens.len() == 1 && KEYWORDS.contains(&first.as_str()) {
+        Operand::Keyword(first)
+    } else if first == "LONG" {
+        Operand::Long(parse_expr(line, &tokens[1..])?)
+    } else {
+        Operand::Value(parse_expr(line, tokens)?)
+    };
+    Ok(Arg { operand, col })
+}
+
+// Decode a sprite row such as "##..#..#", "1100100" or a 16 pixel wide
+// SUPER-CHIP row into bytes
+fn parse_sprite_row(line: usize, token: &Token) -> Result<Vec<u8>, AsmError> {
+    let text = &token.text;
+    if text.is_empty() || text.len() % 8 != 0 {
+        return err(line, token.col, format!("sprite row '{}' must be 8 or 16 pixels wide", text));
+    }
+
+    let mut bytes = vec![0u8; text.len() / 8];
+    for (idx, c) in text.chars().enumerate() {
+        match c {
+            '#' | '1' | 'X' | 'x' => bytes[idx / 8] |= 0x80 >> (idx % 8),
+            '.' | '0' | '_' => {},
+            _ => return err(line, token.col + idx, format!("invalid sprite pixel '{}'", c)),
+        }
+    }
+    Ok(bytes)
+}
+
+struct Assembler {
+    lines: Vec<Line>,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    addr: usize,
+}
+
+impl Assembler {
+    // Make sure a new label or constant name is usable
+    fn check_name(&self, line: usize, token: &Token) -> Result<(), AsmError> {
+        let name = &token.text;
+        if !is_ident(name) || is_reserved(name) {
+            return err(line, token.col, format!("invalid name '{}'", name));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return err(line, token.col, format!("'{}' is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn eval(&self, line: usize, expr: &Expr) -> Result<i64, AsmError> {
+        let mut total = 0i64;
+        for &(negative, ref token) in &expr.terms {
+            let value = match parse_number(&token.text) {
+                Some(value) => value,
+                None => match self.constants.get(&token.text) {
+                    Some(value) => *value,
+                    None => match self.labels.get(&token.text) {
+                        Some(addr) => *addr as i64,
+                        None => return err(line, token.col, format!("undefined name '{}'", token.text)),
+                    },
+                },
+            };
+            let sum = if negative { total.checked_sub(value) } else { total.checked_add(value) };
+            total = match sum {
+                Some(sum) => sum,
+                None => return err(line, token.col, format!("expression overflows at '{}'", token.text)),
+            };
+        }
+        Ok(total)
+    }
+
+    // Evaluate and range check a value
+    fn value(&self, line: usize, expr: &Expr, min: i64, max: i64, what: &str) -> Result<i64, AsmError> {
+        let value = self.eval(line, expr)?;
+        if value < min || value > max {
+            return err(line, expr.col, format!("{} {} out of range ({}..{})", what, value, min, max));
+        }
+        Ok(value)
+    }
+
+    // First pass: parse a line and assign addresses
+    fn parse_line(&mut self, line: usize, text: &str) -> Result<(), AsmError> {
+        let mut tokens = tokenize(text);
+
+        if let Some(first) = tokens.first().cloned() {
+            if first.text.ends_with(':') && !first.text.starts_with(':') && first.text.len() > 1 {
+                let name = Token {
+                    text: first.text[..first.text.len() - 1].to_string(),
+                    col: first.col,
+                };
+                self.check_name(line, &name)?;
+                self.labels.insert(name.text, self.addr);
+                tokens.remove(0);
+            }
+        }
+
+        let head = match tokens.first() {
+            Some(head) => head.clone(),
+            None => return Ok(()),
+        };
+        let rest = &tokens[1..];
+
+        let (item, size) = match head.text.to_uppercase().as_str() {
+            ":CONST" => {
+                if rest.len() < 2 {
+                    return err(line, head.col, ":const needs a name and a value".to_string());
+                }
+                // Constants must be defined before use so they can be
+                // evaluated straight away
+                self.check_name(line, &rest[0])?;
+                let value = self.eval(line, &parse_expr(line, &rest[1..])?)?;
+                self.constants.insert(rest[0].text.clone(), value);
+                return Ok(());
+            },
+            ":BYTE" | "DB" => {
+                let exprs = split_args(line, rest)?.iter()
+                    .map(|arg| parse_expr(line, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if exprs.is_empty() {
+                    return err(line, head.col, "missing data".to_string());
+                }
+                let len = exprs.len();
+                (Item::Bytes(exprs), len)
+            },
+            "DW" => {
+                let exprs = split_args(line, rest)?.iter()
+                    .map(|arg| parse_expr(line, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if exprs.is_empty() {
+                    return err(line, head.col, "missing data".to_string());
+                }
+                let len = exprs.len() * 2;
+                (Item::Words(exprs), len)
+            },
+            ":SPRITE" => {
+                let mut bytes = Vec::new();
+                for token in rest.iter().filter(|t| t.text != ",") {
+                    bytes.extend(parse_sprite_row(line, token)?);
+                }
+                if bytes.is_empty() {
+                    return err(line, head.col, "missing sprite rows".to_string());
+                }
+                let len = bytes.len();
+                (Item::Raw(bytes), len)
+            },
+            _ if head.text.starts_with(':') => {
+                return err(line, head.col, format!("unknown directive '{}'", head.text));
+            },
+            _ => {
+                let args = split_args(line, rest)?.iter()
+                    .map(|arg| parse_arg(line, arg))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let long = args.iter().any(|arg| matches!(arg.operand, Operand::Long(_)));
+                (Item::Inst(head, args), if long { 4 } else { 2 })
+            },
+        };
+
+        self.lines.push(Line { line, addr: self.addr, item });
+        self.addr += size;
+        Ok(())
+    }
+
+    fn instruction(&self, line: usize, mnemonic: &Token, args: &[Arg]) -> Result<Instruction, AsmError> {
+        use self::Instruction::*;
+        use self::Operand::*;
+
+        let addr = |expr: &Expr| self.value(line, expr, 0, 0xfff, "address").map(|v| v as u16);
+        let byte = |expr: &Expr| self.value(line, expr, -128, 0xff, "byte").map(|v| v as u8);
+        let nibble = |expr: &Expr| self.value(line, expr, 0, 0xf, "value").map(|v| v as u8);
+        let ops: Vec<&Operand> = args.iter().map(|arg| &arg.operand).collect();
+        let kw = |op: &Operand, name: &str| match *op {
+            Keyword(ref k) => k == name,
+            _ => false,
+        };
+
+        let upper = mnemonic.text.to_uppercase();
+        let inst = match (upper.as_str(), ops.as_slice()) {
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("SCR", []) => Scr,
+            ("SCL", []) => Scl,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Low,
+            ("HIGH", []) => High,
+            ("AUDIO", []) => Audio,
+            ("SYS", [Value(a)]) => Sys(addr(a)?),
+            ("JP", [Value(a)]) => Jp(addr(a)?),
+            ("JP", [Reg(0), Value(a)]) => JpOffset(addr(a)?),
+            ("CALL", [Value(a)]) => Call(addr(a)?),
+            ("SE", [Reg(x), Reg(y)]) => SeReg(*x, *y),
+            ("SE", [Reg(x), Value(kk)]) => SeByte(*x, byte(kk)?),
+            ("SNE", [Reg(x), Reg(y)]) => SneReg(*x, *y),
+            ("SNE", [Reg(x), Value(kk)]) => SneByte(*x, byte(kk)?),
+            ("LD", [Reg(x), Reg(y)]) => LdReg(*x, *y),
+            ("LD", [Reg(x), Value(kk)]) => LdByte(*x, byte(kk)?),
+            ("LD", [i, Value(a)]) if kw(i, "I") => LdI(addr(a)?),
+            ("LD", [i, Long(a)]) if kw(i, "I") => {
+                LdILong(self.value(line, a, 0, 0xffff, "address")? as u16)
+            },
+            ("LD", [Reg(x), k]) if kw(k, "DT") => LdRegDt(*x),
+            ("LD", [Reg(x), k]) if kw(k, "K") => LdRegKey(*x),
+            ("LD", [Reg(x), k]) if kw(k, "[I]") => LdRegMem(*x),
+            ("LD", [Reg(x), k]) if kw(k, "R") => LdRegR(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "DT") => LdDtReg(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "ST") => LdStReg(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "F") => LdF(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "HF") => LdHf(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "B") => LdB(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "[I]") => LdMemReg(*x),
+            ("LD", [k, Reg(x)]) if kw(k, "R") => LdRReg(*x),
+            ("ADD", [Reg(x), Reg(y)]) => AddReg(*x, *y),
+            ("ADD", [Reg(x), Value(kk)]) => AddByte(*x, byte(kk)?),
+            ("ADD", [i, Reg(x)]) if kw(i, "I") => AddIReg(*x),
+            ("OR", [Reg(x), Reg(y)]) => Or(*x, *y),
+            ("AND", [Reg(x), Reg(y)]) => And(*x, *y),
+            ("XOR", [Reg(x), Reg(y)]) => Xor(*x, *y),
+            ("SUB", [Reg(x), Reg(y)]) => Sub(*x, *y),
+            ("SUBN", [Reg(x), Reg(y)]) => Subn(*x, *y),
+            // With VY omitted shift VX in place whatever the quirk
+            ("SHR", [Reg(x)]) => Shr(*x, *x),
+            ("SHR", [Reg(x), Reg(y)]) => Shr(*x, *y),
+            ("SHL", [Reg(x)]) => Shl(*x, *x),
+            ("SHL", [Reg(x), Reg(y)]) => Shl(*x, *y),
+            ("RND", [Reg(x), Value(kk)]) => Rnd(*x, byte(kk)?),
+            ("DRW", [Reg(x), Reg(y), Value(n)]) => Drw(*x, *y, nibble(n)?),
+            ("SKP", [Reg(x)]) => Skp(*x),
+            ("SKNP", [Reg(x)]) => Sknp(*x),
+            ("SCD", [Value(n)]) => Scd(nibble(n)?),
+            ("SCU", [Value(n)]) => Scu(nibble(n)?),
+            ("SAVE", [Reg(x), Reg(y)]) => LdRangeMem(*x, *y),
+            ("LOAD", [Reg(x), Reg(y)]) => LdMemRange(*x, *y),
+            ("PLANE", [Value(n)]) => Plane(nibble(n)?),
+            ("PITCH", [Reg(x)]) => Pitch(*x),
+            (known, _) if is_mnemonic(known) => {
+                let col = args.first().map_or(mnemonic.col, |arg| arg.col);
+                return err(line, col, format!("invalid operands for {}", known));
+            },
+            _ => return err(line, mnemonic.col, format!("unknown instruction '{}'", mnemonic.text)),
+        };
+        Ok(inst)
+    }
+}
+
+fn is_mnemonic(text: &str) -> bool {
+    ["CLS", "RET", "SCR", "SCL", "EXIT", "LOW", "HIGH", "AUDIO", "SYS", "JP", "CALL",
+     "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND",
+     "DRW", "SKP", "SKNP", "SCD", "SCU", "SAVE", "LOAD", "PLANE", "PITCH"].contains(&text)
+}
+
+/// Assemble source into a rom to be loaded at `ORIGIN`
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut asm = Assembler {
+        lines: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        addr: ORIGIN,
+    };
+
+    for (idx, text) in source.lines().enumerate() {
+        asm.parse_line(idx + 1, text)?;
+    }
+
+    // Second pass, now every label is known
+    let mut out = Vec::new();
+    for line in &asm.lines {
+        debug_assert_eq!(line.addr, ORIGIN + out.len());
+        match line.item {
+            Item::Inst(ref mnemonic, ref args) => {
+                out.extend(asm.instruction(line.line, mnemonic, args)?.encode());
+            },
+            Item::Bytes(ref exprs) => {
+                for expr in exprs {
+                    out.push(asm.value(line.line, expr, -128, 0xff, "byte")? as u8);
+                }
+            },
+            Item::Words(ref exprs) => {
+                for expr in exprs {
+                    let word = asm.value(line.line, expr, -0x8000, 0xffff, "word")? as u16;
+                    out.push((word >> 8) as u8);
+                    out.push(word as u8);
+                }
+            },
+            Item::Raw(ref bytes) => out.extend_from_slice(bytes),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm::{self, Instruction};
+    use octo;
+    use std::fs;
+
+    fn error_at(source: &str) -> (usize, usize, String) {
+        let e = assemble(source).unwrap_err();
+        (e.line, e.column, e.message)
+    }
+
+    #[test]
+    fn instructions() {
+        let rom = assemble("start:\n    LD V0, 0x2A\n    ADD I, V3\n    DRW V1, V2, 5\n\
+                            SHR V4\n    JP V0, start\n    LD I, LONG 0x1234 ; far away\n")
+            .unwrap();
+        assert_eq!(rom, [0x60, 0x2a, 0xf3, 0x1e, 0xd1, 0x25, 0x84, 0x46, 0xb2, 0x00,
+                         0xf0, 0x00, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn forward_labels_and_long_loads() {
+        let rom = assemble("    LD I, LONG end\n    JP end\nend:\n    JP end\n").unwrap();
+        assert_eq!(rom, [0xf0, 0x00, 0x02, 0x06, 0x12, 0x06, 0x12, 0x06]);
+    }
+
+    #[test]
+    fn constants() {
+        let rom = assemble(":const SPEED 3\n:const FAST SPEED + 2\n    LD V0, FAST - 1\n\
+                            LD V1, -SPEED\n").unwrap();
+        assert_eq!(rom, [0x60, 0x04, 0x61, 0xfd]);
+    }
+
+    #[test]
+    fn data() {
+        let rom = assemble(":byte 1, 2, -1\nDB 0xFF\nDW 0x1234, label\nlabel: CLS\n").unwrap();
+        assert_eq!(rom, [0x01, 0x02, 0xff, 0xff, 0x12, 0x34, 0x02, 0x08, 0x00, 0xe0]);
+    }
+
+    #[test]
+    fn sprites() {
+        let rom = assemble(":sprite ##....## 1..1..1.\n:sprite ################\n").unwrap();
+        assert_eq!(rom, [0xc3, 0x92, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn error_locations() {
+        assert_eq!(error_at("    CLS\n    JP nowhere\n"),
+                   (2, 8, "undefined name 'nowhere'".to_string()));
+        assert_eq!(error_at("  LD V0, 0x100"),
+                   (1, 10, "byte 256 out of range (-128..255)".to_string()));
+        assert_eq!(error_at("  ADD V1"), (1, 7, "invalid operands for ADD".to_string()));
+        assert_eq!(error_at("  FOO V0"), (1, 3, "unknown instruction 'FOO'".to_string()));
+        assert_eq!(error_at("foo: CLS\nfoo: RET"), (2, 1, "'foo' is already defined".to_string()));
+        assert_eq!(error_at(":sprite ##.#"),
+                   (1, 9, "sprite row '##.#' must be 8 or 16 pixels wide".to_string()));
+        assert_eq!(error_at(":sprite ##.#..?."), (1, 15, "invalid sprite pixel '?'".to_string()));
+        assert_eq!(error_at("  :bogus 1"), (1, 3, "unknown directive ':bogus'".to_string()));
+        assert_eq!(error_at("DB 1,"), (1, 5, "missing operand after ','".to_string()));
+        assert_eq!(error_at(":const X\n"), (1, 1, ":const needs a name and a value".to_string()));
+        assert_eq!(error_at(":const BIG 0x7FFFFFFFFFFFFFFF\n  LD V0, BIG + 1"),
+                   (2, 16, "expression overflows at '1'".to_string()));
+        assert_eq!(error_at(":const LOW -0x7FFFFFFFFFFFFFFF - 1\n  LD V0, LOW - 1"),
+                   (2, 16, "expression overflows at '1'".to_string()));
+    }
+
+    #[test]
+    fn every_instruction_reassembles() {
+        for word in 0..=0xffffu16 {
+            let inst = disasm::decode(word);
+            if let Instruction::LdILong(_) = inst {
+                continue;
+            }
+            let rom = assemble(&inst.to_string())
+                .unwrap_or_else(|e| panic!("{:04X} ({}) doesn't assemble: {}", word, inst, e));
+            assert_eq!(rom, [(word >> 8) as u8, word as u8], "{}", inst);
+        }
+    }
+
+    #[test]
+    fn flow_listing_round_trips_test_roms() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/roms");
+        let mut checked = 0;
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().map_or(true, |ext| ext != "8o") {
+                continue;
+            }
+            let rom = octo::compile(&fs::read_to_string(&path).unwrap()).unwrap();
+            let listing = disasm::flow_listing(&rom, ORIGIN);
+            let reassembled = assemble(&listing)
+                .unwrap_or_else(|e| panic!("{} listing doesn't assemble: {}\n{}",
+                                           path.display(), e, listing));
+            assert!(reassembled == rom, "{} doesn't round trip\n{}", path.display(), listing);
+            checked += 1;
+        }
+        assert!(checked > 0);
+    }
+}
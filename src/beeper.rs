@@ -0,0 +1,165 @@
+use sdl2::AudioSubsystem;
+use chip8::AUDIO_PATTERN_LEN;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::str::FromStr;
+
+const SAMPLE_RATE: i32 = 44100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Waveform, String> {
+        match s {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            _ => Err(format!("unknown waveform '{}' (square, sine, triangle)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BeeperConfig {
+    pub frequency: f32,
+    pub waveform: Waveform,
+    // 0.0 - 1.0
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for BeeperConfig {
+    fn default() -> BeeperConfig {
+        BeeperConfig {
+            frequency: 440.0,
+            waveform: Waveform::Square,
+            volume: 0.25,
+            muted: false,
+        }
+    }
+}
+
+// An XO-CHIP audio pattern, played back one bit at a time as a 1-bit
+// waveform
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Pattern {
+    bits: [u8; AUDIO_PATTERN_LEN],
+    rate: f32,
+}
+
+struct Tone {
+    phase: f32,
+    phase_inc: f32,
+    waveform: Waveform,
+    volume: f32,
+    sample_rate: f32,
+    pattern: Option<Pattern>,
+}
+
+impl Tone {
+    fn pattern_sample(&mut self, pattern: Pattern) -> f32 {
+        let bit_count = (AUDIO_PATTERN_LEN * 8) as f32;
+        let bit = (self.phase * bit_count) as usize % (AUDIO_PATTERN_LEN * 8);
+        let set = pattern.bits[bit / 8] & (0x80 >> (bit % 8)) != 0;
+
+        // phase covers the whole 128 bit pattern
+        self.phase = (self.phase + pattern.rate / (bit_count * self.sample_rate)) % 1.0;
+        if set { 1.0 } else { -1.0 }
+    }
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            if let Some(pattern) = self.pattern {
+                *x = self.pattern_sample(pattern) * self.volume;
+                continue;
+            }
+
+            let sample = match self.waveform {
+                Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+                Waveform::Sine => (self.phase * 2.0 * ::std::f32::consts::PI).sin(),
+                Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            };
+            *x = sample * self.volume;
+            self.phase = (self.phase + self.phase_inc) % 1.0;
+        }
+    }
+}
+
+/// Plays a continuous tone while the core's sound timer is running.
+pub struct Beeper {
+    device: AudioDevice<Tone>,
+    playing: bool,
+    muted: bool,
+    pattern: Option<Pattern>,
+}
+
+impl Beeper {
+    pub fn new(audio: &AudioSubsystem, config: BeeperConfig) -> Result<Beeper, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio.open_playback(None, &spec, |spec| {
+            Tone {
+                phase: 0.0,
+                phase_inc: config.frequency / spec.freq as f32,
+                waveform: config.waveform,
+                volume: config.volume.clamp(0.0, 1.0),
+                sample_rate: spec.freq as f32,
+                pattern: None,
+            }
+        })?;
+
+        Ok(Beeper {
+            device,
+            playing: false,
+            muted: config.muted,
+            pattern: None,
+        })
+    }
+
+    /// Start or stop the tone, called once per frame with the state of
+    /// the sound timer.
+    pub fn update(&mut self, active: bool) {
+        let play = active && !self.muted;
+        if play == self.playing {
+            return;
+        }
+
+        if play {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.playing = play;
+    }
+
+    /// Switch to (or away from) playing an XO-CHIP audio pattern at the
+    /// given rate in bits per second instead of the configured tone.
+    pub fn set_pattern(&mut self, pattern: Option<([u8; AUDIO_PATTERN_LEN], f32)>) {
+        let pattern = pattern.map(|(bits, rate)| Pattern { bits, rate });
+        if pattern == self.pattern {
+            return;
+        }
+
+        self.device.lock().pattern = pattern;
+        self.pattern = pattern;
+    }
+
+    pub fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
@@ -1,9 +1,33 @@
+#[cfg(feature = "std")]
 extern crate rand;
 
-use chip8::rand::Rng;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+use core::str::FromStr;
+use disasm;
+use quirks::{IndexIncrement, Quirks};
+use state::{StateError, StateReader, StateWriter};
 
 pub const SCREEN_X: usize = 64;
 pub const SCREEN_Y: usize = 32;
+/// SUPER-CHIP high resolution mode dimensions
+pub const HIRES_SCREEN_X: usize = 128;
+pub const HIRES_SCREEN_Y: usize = 64;
+/// Number of XO-CHIP bitplanes, each pixel holds one bit per plane
+pub const PLANES: usize = 4;
+/// Size of the XO-CHIP audio pattern buffer in bytes
+pub const AUDIO_PATTERN_LEN: usize = 16;
+/// Rate at which the delay and sound timers count down
+pub const TIMER_FREQ: u64 = 60;
+// XO-CHIP pitch steps are 48ths of an octave, 2^(1/48)
+const PITCH_STEP: f32 = 1.014_545_3;
+/// Where programs are normally loaded and start executing
+pub const PROGRAM_START: u16 = 0x200;
+/// Where ETI-660 programs are loaded and start executing
+pub const ETI660_PROGRAM_START: u16 = 0x600;
 const CHAR_SPRITES: [u8; 80] = [0xf0, 0x90, 0x90, 0x90, 0xf0,
                                 0x20, 0x60, 0x20, 0x20, 0x70,
                                 0xf0, 0x10, 0xf0, 0x80, 0xf0,
@@ -20,56 +44,518 @@ const CHAR_SPRITES: [u8; 80] = [0xf0, 0x90, 0x90, 0x90, 0xf0,
                                 0xe0, 0x90, 0x90, 0x90, 0xe0,
                                 0xf0, 0x80, 0xf0, 0x80, 0xf0,
                                 0xf0, 0x80, 0xf0, 0x80, 0x80];
+// SUPER-CHIP 8x10 hex digits, stored straight after the small font
+const BIG_CHAR_ADDR: usize = 0x50;
+const BIG_CHAR_SPRITES: [u8; 160] = [0xff, 0xff, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff,
+                                     0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff,
+                                     0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff,
+                                     0xff, 0xff, 0x03, 0x03, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff,
+                                     0xc3, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0x03, 0x03,
+                                     0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff,
+                                     0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff,
+                                     0xff, 0xff, 0x03, 0x03, 0x06, 0x0c, 0x18, 0x18, 0x18, 0x18,
+                                     0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff,
+                                     0xff, 0xff, 0xc3, 0xc3, 0xff, 0xff, 0x03, 0x03, 0xff, 0xff,
+                                     0x7e, 0xff, 0xc3, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3,
+                                     0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc, 0xc3, 0xc3, 0xfc, 0xfc,
+                                     0x3c, 0xff, 0xc3, 0xc0, 0xc0, 0xc0, 0xc0, 0xc3, 0xff, 0x3c,
+                                     0xfc, 0xfe, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xc3, 0xfe, 0xfc,
+                                     0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff,
+                                     0xff, 0xff, 0xc0, 0xc0, 0xff, 0xff, 0xc0, 0xc0, 0xc0, 0xc0];
+
+/// Faults raised by the core while fetching or executing an instruction.
+/// Once one of these is returned the core is left as it was at the point
+/// of the fault so the frontend can inspect or reset it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreError {
+    UnknownOpcode { pc: u16, opcode: u16 },
+    PcOutOfRange(u16),
+    StackOverflow { pc: u16 },
+    StackUnderflow { pc: u16 },
+    MemoryOutOfBounds { pc: u16, addr: usize },
+    InvalidKey { pc: u16, key: u8 },
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CoreError::UnknownOpcode { pc, opcode } =>
+                write!(f, "unknown opcode 0x{:04x} at 0x{:04x}", opcode, pc),
+            CoreError::PcOutOfRange(pc) =>
+                write!(f, "program counter out of range: 0x{:04x}", pc),
+            CoreError::StackOverflow { pc } =>
+                write!(f, "stack overflow at 0x{:04x}", pc),
+            CoreError::StackUnderflow { pc } =>
+                write!(f, "stack underflow at 0x{:04x}", pc),
+            CoreError::MemoryOutOfBounds { pc, addr } =>
+                write!(f, "memory access out of bounds (0x{:x}) at 0x{:04x}",
+                       addr, pc),
+            CoreError::InvalidKey { pc, key } =>
+                write!(f, "key 0x{:02x} out of range at 0x{:04x}", key, pc),
+        }
+    }
+}
+
+impl error::Error for CoreError {}
+
+/// Reasons a ROM can't be loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// The ROM doesn't fit in the memory after the load address
+    TooLarge { size: usize, max: usize },
+    /// The load address is in the interpreter area or past the end of memory
+    InvalidAddress { addr: u16 },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::TooLarge { size, max } =>
+                write!(f, "ROM is {} bytes, only {} fit in memory", size, max),
+            LoadError::InvalidAddress { addr } =>
+                write!(f, "ROM can't be loaded at 0x{:04x}", addr),
+        }
+    }
+}
+
+impl error::Error for LoadError {}
+
+/// How much memory the core has. Addresses are 16 bits so 64 KiB is as
+/// far as it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemorySize {
+    /// 4 KiB, as on the COSMAC VIP and SUPER-CHIP
+    Classic,
+    /// 64 KiB, as on XO-CHIP
+    Extended,
+}
+
+impl MemorySize {
+    pub fn bytes(&self) -> usize {
+        match *self {
+            MemorySize::Classic => 0x1000,
+            MemorySize::Extended => 0x10000,
+        }
+    }
+}
+
+impl FromStr for MemorySize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<MemorySize, String> {
+        match s {
+            "4k" | "4K" => Ok(MemorySize::Classic),
+            "64k" | "64K" => Ok(MemorySize::Extended),
+            _ => Err(format!("unknown memory size '{}' (4k, 64k)", s)),
+        }
+    }
+}
+
+/// The family of interpreter being emulated. This decides which instruction
+/// set extensions are available and how much memory there is by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Machine {
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Machine {
+    pub fn memory_size(&self) -> MemorySize {
+        match *self {
+            Machine::XoChip => MemorySize::Extended,
+            _ => MemorySize::Classic,
+        }
+    }
+
+    // Identifier stored in save states
+    fn id(&self) -> u8 {
+        match *self {
+            Machine::Chip8 => 0,
+            Machine::SuperChip => 1,
+            Machine::XoChip => 2,
+        }
+    }
+
+    fn has_schip(&self) -> bool {
+        *self != Machine::Chip8
+    }
+
+    fn has_xochip(&self) -> bool {
+        *self == Machine::XoChip
+    }
+}
+
+impl FromStr for Machine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Machine, String> {
+        match s {
+            "chip8" => Ok(Machine::Chip8),
+            "schip" | "superchip" => Ok(Machine::SuperChip),
+            "xochip" => Ok(Machine::XoChip),
+            _ => Err(format!("unknown machine '{}' (chip8, schip, xochip)", s)),
+        }
+    }
+}
+
+/// State of an in-progress Fx0A. Like the COSMAC VIP the instruction only
+/// completes once a key has been pressed *and* released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyWait {
+    reg: usize,
+    pressed: Option<usize>,
+}
+
+/// A data memory access made by an instruction, reported to debuggers.
+/// Instruction fetches are not included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemAccess {
+    pub addr: usize,
+    pub write: bool,
+}
+
+/// Where CXNN gets its random numbers from, see `Core::set_random`.
+pub trait Random {
+    fn random_byte(&mut self) -> u8;
+}
+
+#[cfg(feature = "std")]
+impl Random for rand::ThreadRng {
+    fn random_byte(&mut self) -> u8 {
+        use chip8::rand::Rng;
+        self.gen()
+    }
+}
+
+/// A small xorshift generator. It's the default without `std`, and handy
+/// anywhere a run needs to be repeatable.
+pub struct XorShift(u32);
+
+impl XorShift {
+    pub fn new(seed: u32) -> XorShift {
+        // An all zero state would only ever produce zeroes
+        XorShift(if seed == 0 { 0x2545_f491 } else { seed })
+    }
+}
+
+impl Random for XorShift {
+    fn random_byte(&mut self) -> u8 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 >> 24) as u8
+    }
+}
+
+#[cfg(feature = "std")]
+fn default_random() -> Box<dyn Random> {
+    Box::new(rand::thread_rng())
+}
+
+#[cfg(not(feature = "std"))]
+fn default_random() -> Box<dyn Random> {
+    Box::new(XorShift::new(0))
+}
+
+/// Receives every instruction the core executes, see `Core::set_tracer`.
+pub trait Tracer {
+    /// Called once `opcode` has executed. `before` holds the registers as
+    /// they were beforehand, so `before.pc` is where it was fetched from.
+    fn instruction(&mut self, core: &Core, before: &Registers, opcode: u16);
+}
 
+/// The CPU registers, as seen by debuggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub v: [u8; 0x10],
+    pub i: u16,
+    pub pc: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
 
 pub struct Core {
-    pub frame_buffer: [u8; (SCREEN_X / 8) * SCREEN_Y],
-    memory: [u8; 0x1000],
+    // One byte per pixel, row major, sized for the current resolution.
+    // Bit n of each pixel is its value in bitplane n.
+    frame_buffer: [u8; HIRES_SCREEN_X * HIRES_SCREEN_Y],
+    hires: bool,
+    // Bitmask of the planes affected by drawing and scrolling (FN01)
+    planes: u8,
+    memory: Vec<u8>,
     registers: [u8; 0x10],
     stack: [u16; 0x10],
     keys: [bool; 0x10],
     sp: u8,
     i: u16,
     pc: u16,
+    // Where the ROM was loaded, execution starts here after a reset
+    start: u16,
     dt: u8,
-    st: u8
+    st: u8,
+    key_wait: Option<KeyWait>,
+    // SUPER-CHIP RPL user flags (FX75/FX85)
+    rpl: [u8; 0x10],
+    exited: bool,
+    // XO-CHIP audio pattern buffer (F002) and pitch (FX3A)
+    audio_pattern: Option<[u8; AUDIO_PATTERN_LEN]>,
+    pitch: u8,
+    machine: Machine,
+    quirks: Quirks,
+    // Data accesses made by the last instruction, when being observed
+    observe: bool,
+    accesses: Vec<MemAccess>,
+    cycles: u64,
+    tracer: Option<Box<dyn Tracer>>,
+    random: Box<dyn Random>,
 }
 
 impl Core {
 
+    /// Press one of the 16 keys, ids above 0xF are ignored
     pub fn set_key(&mut self, key_id: usize) {
+        if key_id >= self.keys.len() {
+            return;
+        }
         self.keys[key_id] = true;
+
+        if let Some(ref mut wait) = self.key_wait {
+            if wait.pressed.is_none() {
+                wait.pressed = Some(key_id);
+            }
+        }
     }
 
+    /// Release one of the 16 keys, ids above 0xF are ignored
     pub fn clear_key(&mut self, key_id: usize) {
+        if key_id >= self.keys.len() {
+            return;
+        }
         self.keys[key_id] = false;
+
+        if let Some(wait) = self.key_wait {
+            if wait.pressed == Some(key_id) {
+                self.registers[wait.reg] = key_id as u8;
+                self.key_wait = None;
+            }
+        }
     }
 
-    pub fn load_rom(&mut self, rom: &Vec<u8>) {
-        println!("Loading Rom");
-        for i in 0..rom.len() {
-            self.memory[0x200 + i] = rom[i];
+    /// Current display resolution as (width, height)
+    pub fn resolution(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_SCREEN_X, HIRES_SCREEN_Y)
+        } else {
+            (SCREEN_X, SCREEN_Y)
         }
     }
 
-    fn load_sprites(&mut self) {
-        for i in 0..CHAR_SPRITES.len() {
-            self.memory[i] = CHAR_SPRITES[i];
+    /// The visible framebuffer, one byte per pixel in row major order at
+    /// the current `resolution`. Each pixel holds one bit per bitplane,
+    /// so outside of XO-CHIP it is either 0 or 1.
+    pub fn frame_buffer(&self) -> &[u8] {
+        let (width, height) = self.resolution();
+        &self.frame_buffer[..width * height]
+    }
+
+    /// True once the program has executed a SUPER-CHIP 00FD exit
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    /// The XO-CHIP audio pattern, if one has been loaded, and the rate in
+    /// bits per second it should be played back at.
+    pub fn audio_pattern(&self) -> Option<([u8; AUDIO_PATTERN_LEN], f32)> {
+        // 4000 * 2^((pitch - 64) / 48), split into whole octaves and the
+        // steps left over as core has no powf
+        let steps = self.pitch as i32 - 64;
+        let octaves = steps.div_euclid(48);
+        let base = (0..steps.rem_euclid(48)).fold(4000.0, |rate, _| rate * PITCH_STEP);
+        let rate = if octaves < 0 {
+            base / (1 << -octaves) as f32
+        } else {
+            base * (1 << octaves) as f32
+        };
+        self.audio_pattern.map(|pattern| (pattern, rate))
+    }
+
+    /// True while the sound timer is running and the buzzer should sound
+    pub fn sound_active(&self) -> bool {
+        self.st > 0
+    }
+
+    /// Bitmask of the XO-CHIP planes selected for drawing (FN01)
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// True while execution is halted on an Fx0A waiting for a key to be
+    /// pressed and released.
+    pub fn waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            v: self.registers,
+            i: self.i,
+            pc: self.pc,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+        }
+    }
+
+    /// Overwrite the CPU registers. The stack pointer is limited to the
+    /// depth of the stack.
+    pub fn set_registers(&mut self, regs: &Registers) {
+        self.registers = regs.v;
+        self.i = regs.i;
+        self.pc = regs.pc;
+        self.sp = regs.sp.min(self.stack.len() as u8);
+        self.dt = regs.dt;
+        self.st = regs.st;
+    }
+
+    /// Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// Overwrite memory from a debugger. Returns false, changing nothing,
+    /// if the data does not fit.
+    pub fn write_memory(&mut self, addr: usize, data: &[u8]) -> bool {
+        let end = match addr.checked_add(data.len()) {
+            Some(end) => end,
+            None => return false,
+        };
+        match self.memory.get_mut(addr..end) {
+            Some(dest) => {
+                dest.copy_from_slice(data);
+                true
+            },
+            None => false,
+        }
+    }
+
+    // Address of the instruction currently being executed (fetch has
+    // already advanced pc past it)
+    fn cur_pc(&self) -> u16 {
+        self.pc.wrapping_sub(2)
+    }
+
+    /// Start or stop recording the memory accesses made by each
+    /// instruction, see `accesses`
+    pub fn observe_memory(&mut self, observe: bool) {
+        self.observe = observe;
+        self.accesses.clear();
+    }
+
+    /// Data memory accesses made by the last instruction executed with
+    /// `tick`, while `observe_memory` is enabled
+    pub fn accesses(&self) -> &[MemAccess] {
+        &self.accesses
+    }
+
+    // Read from the instruction stream, which is not reported as a data
+    // access
+    fn read_code(&self, addr: usize) -> Result<u8, CoreError> {
+        match self.memory.get(addr) {
+            Some(val) => Ok(*val),
+            None => Err(CoreError::MemoryOutOfBounds { pc: self.cur_pc(), addr }),
         }
     }
 
-    pub fn new() -> Core {
+    // All data reads and writes made by instructions go through read_mem
+    // and write_mem so debuggers can watch them
+    fn read_mem(&mut self, addr: usize) -> Result<u8, CoreError> {
+        let val = self.read_code(addr)?;
+        if self.observe {
+            self.accesses.push(MemAccess { addr, write: false });
+        }
+        Ok(val)
+    }
+
+    fn write_mem(&mut self, addr: usize, val: u8) -> Result<(), CoreError> {
+        let pc = self.cur_pc();
+        match self.memory.get_mut(addr) {
+            Some(cell) => *cell = val,
+            None => return Err(CoreError::MemoryOutOfBounds { pc, addr }),
+        }
+        if self.observe {
+            self.accesses.push(MemAccess { addr, write: true });
+        }
+        Ok(())
+    }
+
+    /// Load a ROM at `PROGRAM_START`
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), LoadError> {
+        self.load_rom_at(rom, PROGRAM_START)
+    }
+
+    /// Load a ROM at `addr` and start executing from there, both now and
+    /// after a reset. Addresses below `PROGRAM_START` belong to the
+    /// interpreter and are refused.
+    pub fn load_rom_at(&mut self, rom: &[u8], addr: u16) -> Result<(), LoadError> {
+        let start = addr as usize;
+        if addr < PROGRAM_START || start >= self.memory.len() {
+            return Err(LoadError::InvalidAddress { addr });
+        }
+        let max = self.memory.len() - start;
+        if rom.len() > max {
+            return Err(LoadError::TooLarge { size: rom.len(), max });
+        }
+
+        debug!("loading {} byte rom at 0x{:04x}", rom.len(), addr);
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.start = addr;
+        self.pc = addr;
+        Ok(())
+    }
+
+    fn load_sprites(&mut self) {
+        self.memory[..CHAR_SPRITES.len()].copy_from_slice(&CHAR_SPRITES);
+        self.memory[BIG_CHAR_ADDR..BIG_CHAR_ADDR + BIG_CHAR_SPRITES.len()]
+            .copy_from_slice(&BIG_CHAR_SPRITES);
+    }
+
+    pub fn new(machine: Machine, quirks: Quirks) -> Core {
+        Core::with_memory(machine, quirks, machine.memory_size())
+    }
+
+    /// Create a core with a memory size other than the machine's own
+    pub fn with_memory(machine: Machine, quirks: Quirks, memory: MemorySize) -> Core {
         let mut result = Core{
-            frame_buffer: [0u8; (SCREEN_X / 8) * SCREEN_Y],
-            memory: [0u8; 0x1000],
+            frame_buffer: [0u8; HIRES_SCREEN_X * HIRES_SCREEN_Y],
+            hires: false,
+            planes: 0x1,
+            memory: vec![0u8; memory.bytes()],
             registers: [0u8; 0x10],
             stack: [0u16; 0x10],
             keys: [false; 0x10],
             sp: 0,
             i: 0,
-            pc: 0x200,
+            pc: PROGRAM_START,
+            start: PROGRAM_START,
             dt: 0,
-            st: 0
+            st: 0,
+            key_wait: None,
+            rpl: [0u8; 0x10],
+            exited: false,
+            audio_pattern: None,
+            pitch: 64,
+            machine,
+            quirks,
+            observe: false,
+            accesses: Vec::new(),
+            cycles: 0,
+            tracer: None,
+            random: default_random(),
         };
         result.soft_reset();
         result.load_sprites();
@@ -77,63 +563,162 @@ impl Core {
     }
 
     fn op_cls(&mut self, _inst: u16) {
-        println!("Clearing Screen");
-        for i in 0..self.frame_buffer.len() - 1 {
-            self.frame_buffer[i] = 0;
+        let planes = self.planes;
+        for pixel in self.frame_buffer.iter_mut() {
+            *pixel &= !planes;
         }
     }
 
-    fn op_ret(&mut self, _inst: u16) {
-        println!("ret");
-        self.pc = self.stack[self.sp as usize];
+    // Move the selected planes of a pixel, leaving the others in place
+    fn move_pixel(&mut self, from: Option<usize>, to: usize) {
+        let planes = self.planes;
+        let val = from.map_or(0, |idx| self.frame_buffer[idx]) & planes;
+        self.frame_buffer[to] = (self.frame_buffer[to] & !planes) | val;
+    }
+
+    // Shift the selected planes vertically, positive is down
+    fn scroll_vertical(&mut self, shift: isize) {
+        let (width, height) = self.resolution();
+
+        if shift > 0 {
+            let n = shift as usize;
+            for y in (0..height).rev() {
+                for x in 0..width {
+                    let from = if y >= n { Some((y - n) * width + x) } else { None };
+                    self.move_pixel(from, y * width + x);
+                }
+            }
+        } else {
+            let n = (-shift) as usize;
+            for y in 0..height {
+                for x in 0..width {
+                    let from = if y + n < height { Some((y + n) * width + x) } else { None };
+                    self.move_pixel(from, y * width + x);
+                }
+            }
+        }
+    }
+
+    fn op_scd(&mut self, inst: u16) {
+        self.scroll_vertical((inst & 0xf) as isize);
+    }
+
+    fn op_scu(&mut self, inst: u16) {
+        self.scroll_vertical(-((inst & 0xf) as isize));
+    }
+
+    // Shift the selected planes horizontally, positive is right
+    fn scroll_horizontal(&mut self, shift: isize) {
+        let (width, height) = self.resolution();
+
+        for y in 0..height {
+            let row = y * width;
+            if shift > 0 {
+                let n = shift as usize;
+                for x in (0..width).rev() {
+                    let from = if x >= n { Some(row + x - n) } else { None };
+                    self.move_pixel(from, row + x);
+                }
+            } else {
+                let n = (-shift) as usize;
+                for x in 0..width {
+                    let from = if x + n < width { Some(row + x + n) } else { None };
+                    self.move_pixel(from, row + x);
+                }
+            }
+        }
+    }
+
+    fn op_scr(&mut self, _inst: u16) {
+        self.scroll_horizontal(4);
+    }
+
+    fn op_scl(&mut self, _inst: u16) {
+        self.scroll_horizontal(-4);
+    }
+
+    fn op_exit(&mut self, _inst: u16) {
+        self.exited = true;
+    }
+
+    // Switching resolution wipes every plane, not just the selected ones
+    fn op_low(&mut self, _inst: u16) {
+        self.hires = false;
+        self.frame_buffer = [0u8; HIRES_SCREEN_X * HIRES_SCREEN_Y];
+    }
+
+    fn op_high(&mut self, _inst: u16) {
+        self.hires = true;
+        self.frame_buffer = [0u8; HIRES_SCREEN_X * HIRES_SCREEN_Y];
+    }
+
+    fn op_ret(&mut self, _inst: u16) -> Result<(), CoreError> {
+        if self.sp == 0 {
+            return Err(CoreError::StackUnderflow { pc: self.cur_pc() });
+        }
         self.sp -= 1;
+        self.pc = self.stack[self.sp as usize];
+        Ok(())
+    }
+
+    // Skip the next instruction. XO-CHIP's F000 NNNN is four bytes long
+    // so skipping over it needs to step over the extra word.
+    fn skip(&mut self) -> Result<(), CoreError> {
+        let pc = self.pc as usize;
+        let long = self.machine.has_xochip()
+            && self.read_code(pc)? == 0xf0
+            && self.read_code(pc + 1)? == 0x00;
+
+        self.pc = self.pc.wrapping_add(if long { 4 } else { 2 });
+        Ok(())
     }
 
     fn op_jp(&mut self, inst: u16) {
-        println!("jp");
         let tgt_addr = inst & 0xfff;
         self.pc = tgt_addr;
     }
 
-    fn op_call(&mut self, inst: u16) {
-        println!("call");
-        self.sp += 1;
+    fn op_call(&mut self, inst: u16) -> Result<(), CoreError> {
+        if self.sp as usize >= self.stack.len() {
+            return Err(CoreError::StackOverflow { pc: self.cur_pc() });
+        }
         self.stack[self.sp as usize] = self.pc;
+        self.sp += 1;
         self.pc = inst & 0xfff;
+        Ok(())
     }
 
-    fn op_se(&mut self, inst: u16) {
-        println!("se");
+    fn op_se(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
         let comp = (inst & 0xff) as u8;
 
         if self.registers[rx] == comp {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
-    fn op_sne(&mut self, inst: u16) {
-        println!("sne");
+    fn op_sne(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
         let comp = (inst & 0xff) as u8;
 
         if self.registers[rx] != comp {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
-    fn op_sereg(&mut self, inst: u16) {
-        println!("sereg");
+    fn op_sereg(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
         if self.registers[rx] == self.registers[ry] {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
     fn op_ld(&mut self, inst: u16) {
-        println!("ld");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let val = (inst & 0xff) as u8;
 
@@ -141,7 +726,6 @@ impl Core {
     }
 
     fn op_add(&mut self, inst: u16) {
-        println!("add");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let val = inst & 0xff;
 
@@ -151,7 +735,6 @@ impl Core {
     }
 
     fn op_ldreg(&mut self, inst: u16) {
-        println!("ldreg");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
@@ -159,31 +742,36 @@ impl Core {
     }
 
     fn op_or(&mut self, inst: u16) {
-        println!("or");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
-        self.registers[rx] = self.registers[rx] | self.registers[ry];
+        self.registers[rx] |= self.registers[ry];
+        if self.quirks.vf_reset {
+            self.registers[0xf] = 0;
+        }
     }
 
     fn op_and(&mut self, inst: u16) {
-        println!("and");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
-        self.registers[rx] = self.registers[rx] & self.registers[ry];
+        self.registers[rx] &= self.registers[ry];
+        if self.quirks.vf_reset {
+            self.registers[0xf] = 0;
+        }
     }
 
     fn op_xor(&mut self, inst: u16) {
-        println!("xor");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
         let val = self.registers[rx] ^ self.registers[ry];
         self.registers[rx] = val;
+        if self.quirks.vf_reset {
+            self.registers[0xf] = 0;
+        }
     }
 
     fn op_addcarry(&mut self, inst: u16) {
-        println!("addcarry");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
@@ -195,296 +783,617 @@ impl Core {
     }
 
     fn op_sub(&mut self, inst: u16) {
-        println!("sub");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
-        if self.registers[rx] < self.registers[ry] {
-            self.registers[0xf] = 1;
-        } else {
-            self.registers[0xf] = 0;
-        }
-
-        // Use wrapping_sub here to allow for integer overflow
-        self.registers[rx] = self.registers[rx]
-            .wrapping_sub(self.registers[ry]);
+        // VF is NOT borrow, and written last so the flag wins when X is F
+        let (x, y) = (self.registers[rx], self.registers[ry]);
+        self.registers[rx] = x.wrapping_sub(y);
+        self.registers[0xf] = (x >= y) as u8;
     }
 
     fn op_shr(&mut self, inst: u16) {
-        println!("shr");
         let rx = ((inst & 0xf00) >> 8) as usize;
+        let ry = ((inst & 0xf0) >> 4) as usize;
+        let src = if self.quirks.shift_vy { ry } else { rx };
 
-        self.registers[0xf] = self.registers[rx] & 0x1;
-        self.registers[rx] = self.registers[rx] >> 1;
+        // VF is written last so the flag wins when X is F
+        let val = self.registers[src];
+        self.registers[rx] = val >> 1;
+        self.registers[0xf] = val & 0x1;
     }
 
     fn op_subn(&mut self, inst: u16) {
-        println!("subn");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
-        if self.registers[rx] > self.registers[ry] {
-            self.registers[0xf] = 1;
-        } else {
-            self.registers[0xf] = 0;
-        }
-
-        self.registers[rx] = self.registers[rx]
-            .wrapping_sub(self.registers[ry]);
+        let (x, y) = (self.registers[rx], self.registers[ry]);
+        self.registers[rx] = y.wrapping_sub(x);
+        self.registers[0xf] = (y >= x) as u8;
     }
 
     fn op_shl(&mut self, inst: u16) {
-        println!("shl");
         let rx = ((inst & 0xf00) >> 8) as usize;
+        let ry = ((inst & 0xf0) >> 4) as usize;
+        let src = if self.quirks.shift_vy { ry } else { rx };
 
-        self.registers[0xf] = (self.registers[rx] & 0x80) >> 7;
-        self.registers[rx] = self.registers[rx] << 1;
+        let val = self.registers[src];
+        self.registers[rx] = val << 1;
+        self.registers[0xf] = (val & 0x80) >> 7;
     }
 
-    fn op_snereg(&mut self, inst: u16) {
-        println!("snereg");
+    fn op_snereg(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
 
         if self.registers[rx] != self.registers[ry] {
-            self.pc += 2;
+            self.skip()?;
         }
+        Ok(())
     }
 
     fn op_ldi(&mut self, inst: u16) {
-        println!("ldi");
         self.i = inst & 0xfff;
     }
 
     fn op_jp_offset(&mut self, inst: u16) {
-        println!("jp_offset");
-        self.pc = (inst & 0xfff) + self.registers[0] as u16;
+        // BXNN on CHIP-48 and SUPER-CHIP, BNNN everywhere else
+        let rx = if self.quirks.jump_vx {
+            ((inst & 0xf00) >> 8) as usize
+        } else {
+            0
+        };
+        self.pc = (inst & 0xfff) + self.registers[rx] as u16;
     }
 
     fn op_rnd(&mut self, inst: u16) {
-        println!("rnd");
         let rx = ((inst & 0xf00) >> 8) as usize;
         let mask = (inst & 0xff) as u8;
 
-        let rand_val = rand::thread_rng().gen_range(0x00, 0x100) as u8;
+        let rand_val = self.random.random_byte();
         self.registers[rx] = rand_val & (inst as u8 & mask);
     }
 
-    fn op_drw(&mut self, inst: u16) {
-        println!("drw");
+    // XOR a single pixel in one plane, returning true if a lit pixel got
+    // turned off
+    fn xor_pixel(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let (width, _) = self.resolution();
+        let idx = y * width + x;
+        let collision = self.frame_buffer[idx] & plane != 0;
+
+        self.frame_buffer[idx] ^= plane;
+        collision
+    }
+
+    fn op_drw(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
         let ry = ((inst & 0xf0) >> 4) as usize;
-        let n = inst & 0xf;
+        let n = (inst & 0xf) as usize;
+        let (width, height) = self.resolution();
 
-        let x = self.registers[rx];
-        let y = self.registers[ry];
+        // The starting position always wraps, only the parts of the
+        // sprite hanging off the edge are subject to the clip quirk
+        let x = self.registers[rx] as usize % width;
+        let y = self.registers[ry] as usize % height;
+        let clip = self.quirks.clip_sprites;
 
-        let remainder_bits = x % 8;
+        // DXY0 draws a 16x16 SUPER-CHIP sprite, two bytes per row
+        let (rows, row_bytes) = if n == 0 { (16, 2) } else { (n, 1) };
 
         // Clear Vf
         self.registers[0xf] = 0;
-        let cur_idx = (x as usize / (SCREEN_X / 8)) as usize;
-        for offset in 0..n {
-            let y_idx = (y as u16 + offset) as usize;;
-            let sprite_byte = self.memory[(self.i + offset) as usize];
-            let mask = (0xff >> remainder_bits) as u8;
-            let idx = (SCREEN_Y) * (cur_idx) + (y_idx);
-
-            println!("{}, {}", cur_idx, y_idx);
-
-            if self.frame_buffer[idx] & mask != 0 {
-                println!("Collision!");
-                self.registers[0xf] |= 0x1;
-            }
-
-            self.frame_buffer[idx] ^= sprite_byte >> remainder_bits;
 
-            // Handle being on the edge of a sprite
-            if remainder_bits > 0 {
-                // Note the modulus here, if we're on the edge of the
-                // screen, we just wrap to the other side
-                let ovf_idx = (cur_idx + 1) % (SCREEN_X / 8);
-                let ovf_mask = 0xff << remainder_bits;
-                let idx = (SCREEN_Y) * (ovf_idx) + (y_idx);
-
-                println!("OVERFLOW: {}, {}", ovf_idx, y_idx);
+        // Each selected plane consumes its own copy of the sprite data,
+        // one after the other starting at I
+        let mut base = self.i as usize;
+        for plane in (0..PLANES).map(|p| 1u8 << p) {
+            if self.planes & plane == 0 {
+                continue;
+            }
 
-                if (self.frame_buffer[idx] & ovf_mask) != 0 {
-                    self.registers[0xf] = self.registers[0xf] | 0x1;
+            for row in 0..rows {
+                let mut py = y + row;
+                if py >= height {
+                    if clip {
+                        break;
+                    }
+                    py %= height;
                 }
 
-                self.frame_buffer[idx] ^= sprite_byte << (8 - remainder_bits);
+                for byte in 0..row_bytes {
+                    let sprite_byte = self.read_mem(base + row * row_bytes + byte)?;
+
+                    for bit in 0..8 {
+                        if sprite_byte & (0x80 >> bit) == 0 {
+                            continue;
+                        }
+
+                        let mut px = x + byte * 8 + bit;
+                        if px >= width {
+                            if clip {
+                                continue;
+                            }
+                            px %= width;
+                        }
+
+                        if self.xor_pixel(px, py, plane) {
+                            self.registers[0xf] = 1;
+                        }
+                    }
+                }
             }
+            base += rows * row_bytes;
         }
+        Ok(())
     }
 
-    fn op_skp(&mut self, inst: u16) {
-        println!("skp");
+    // The key named by VX for EX9E/EXA1, which only has 16 to choose from
+    fn key_state(&self, inst: u16) -> Result<bool, CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
-        let key = self.registers[rx] as usize;
+        let key = self.registers[rx];
 
-        if self.keys[key] {
-            self.pc += 2;
+        match self.keys.get(key as usize) {
+            Some(pressed) => Ok(*pressed),
+            None => Err(CoreError::InvalidKey { pc: self.cur_pc(), key }),
         }
     }
 
-    fn op_sknp(&mut self, inst: u16) {
-        println!("sknp");
-        let rx = ((inst & 0xf00) >> 8) as usize;
-        let key = self.registers[rx] as usize;
+    fn op_skp(&mut self, inst: u16) -> Result<(), CoreError> {
+        if self.key_state(inst)? {
+            self.skip()?;
+        }
+        Ok(())
+    }
 
-        // TODO: Check key pressed
-        if !self.keys[key] {
-            self.pc += 2;
+    fn op_sknp(&mut self, inst: u16) -> Result<(), CoreError> {
+        if !self.key_state(inst)? {
+            self.skip()?;
         }
+        Ok(())
     }
 
     fn op_ldreg_dt(&mut self, inst: u16) {
-        println!("ldreg_dt");
         let rx = ((inst & 0xf00) >> 8) as usize;
 
         self.registers[rx] = self.dt
     }
 
     fn op_ldreg_key(&mut self, inst: u16) {
-        println!("ldreg_key");
         let rx = ((inst & 0xf00) >> 8) as usize;
 
-        // TODO: Get key
-        self.registers[rx] = 0;
+        // Execution halts here until set_key/clear_key see a full
+        // press and release
+        self.key_wait = Some(KeyWait { reg: rx, pressed: None });
     }
 
     fn op_lddt_reg(&mut self, inst: u16) {
-        println!("lddt_reg");
         let rx = ((inst & 0xf00) >> 8) as usize;
 
         self.dt = self.registers[rx];
     }
 
     fn op_ldst_reg(&mut self, inst: u16) {
-        println!("ldst_reg");
         let rx = ((inst & 0xf00) >> 8) as usize;
 
         self.st = self.registers[rx];
     }
 
     fn op_addi_reg(&mut self, inst: u16) {
-        println!("addi_reg");
         let rx = ((inst & 0xf00) >> 8) as usize;
 
-        self.i += self.registers[rx] as u16;
+        self.i = self.i.wrapping_add(self.registers[rx] as u16);
     }
 
     fn op_ldf(&mut self, inst: u16) {
-        println!("ldf");
         let rx = ((inst & 0xf00) >> 8) as usize;
 
-        self.i = self.registers[rx] as u16 * 5;
+        self.i = (self.registers[rx] & 0xf) as u16 * 5;
     }
 
-    fn op_ldb(&mut self, inst: u16) {
-        println!("ldb");
+    fn op_ldhf(&mut self, inst: u16) {
         let rx = ((inst & 0xf00) >> 8) as usize;
-        let mut val = self.registers[rx];
 
-        for i in 0..3 {
-            let digit = val % 10;
-            val = val / 10;
-            self.memory[(self.i + (2 - i)) as usize] = digit
+        self.i = (BIG_CHAR_ADDR + (self.registers[rx] & 0xf) as usize * 10) as u16;
+    }
+
+    fn op_ldb(&mut self, inst: u16) -> Result<(), CoreError> {
+        let rx = ((inst & 0xf00) >> 8) as usize;
+        let val = self.registers[rx];
+
+        // Hundreds first, so a fault names the first address out of range
+        for (offset, digit) in [val / 100, val / 10 % 10, val % 10].iter().enumerate() {
+            let addr = self.i as usize + offset;
+            self.write_mem(addr, *digit)?;
         }
+        Ok(())
     }
 
-    fn op_ldreg_mem(&mut self, inst: u16) {
-        println!("ldreg_mem");
+    fn op_ldreg_mem(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
 
         for i in 0..rx + 1 {
-            self.memory[(self.i + i as u16) as usize] = self.registers[i];
+            let addr = self.i as usize + i;
+            let val = self.registers[i];
+            self.write_mem(addr, val)?;
         }
+        self.advance_index(rx);
+        Ok(())
     }
 
-    fn op_ldmem_reg(&mut self, inst: u16) {
-        println!("ldmem_reg");
+    fn op_ldmem_reg(&mut self, inst: u16) -> Result<(), CoreError> {
         let rx = ((inst & 0xf00) >> 8) as usize;
 
         for i in 0..rx + 1 {
-            self.registers[i] = self.memory[(self.i + i as u16) as usize];
+            self.registers[i] = self.read_mem(self.i as usize + i)?;
         }
+        self.advance_index(rx);
+        Ok(())
+    }
+
+    fn op_ldr_reg(&mut self, inst: u16) {
+        let rx = ((inst & 0xf00) >> 8) as usize;
+
+        self.rpl[..rx + 1].copy_from_slice(&self.registers[..rx + 1]);
+    }
+
+    fn op_ldreg_r(&mut self, inst: u16) {
+        let rx = ((inst & 0xf00) >> 8) as usize;
+
+        self.registers[..rx + 1].copy_from_slice(&self.rpl[..rx + 1]);
+    }
+
+    // XO-CHIP register ranges run from X to Y in either direction
+    fn reg_range(inst: u16) -> Vec<usize> {
+        let rx = ((inst & 0xf00) >> 8) as usize;
+        let ry = ((inst & 0xf0) >> 4) as usize;
+
+        if rx <= ry {
+            (rx..ry + 1).collect()
+        } else {
+            (ry..rx + 1).rev().collect()
+        }
+    }
+
+    fn op_ldrange_mem(&mut self, inst: u16) -> Result<(), CoreError> {
+        let base = self.i as usize;
+
+        for (offset, reg) in Core::reg_range(inst).into_iter().enumerate() {
+            let val = self.registers[reg];
+            self.write_mem(base + offset, val)?;
+        }
+        Ok(())
+    }
+
+    fn op_ldmem_range(&mut self, inst: u16) -> Result<(), CoreError> {
+        let base = self.i as usize;
+
+        for (offset, reg) in Core::reg_range(inst).into_iter().enumerate() {
+            self.registers[reg] = self.read_mem(base + offset)?;
+        }
+        Ok(())
+    }
+
+    fn op_ldi_long(&mut self, _inst: u16) -> Result<(), CoreError> {
+        let pc = self.pc as usize;
+        let addr = (self.read_code(pc)? as u16) << 8 | self.read_code(pc + 1)? as u16;
+
+        self.i = addr;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(())
+    }
+
+    fn op_plane(&mut self, inst: u16) {
+        self.planes = ((inst & 0xf00) >> 8) as u8;
+    }
+
+    fn op_audio(&mut self, _inst: u16) -> Result<(), CoreError> {
+        let mut pattern = [0u8; AUDIO_PATTERN_LEN];
+
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = self.read_mem(self.i as usize + offset)?;
+        }
+        self.audio_pattern = Some(pattern);
+        Ok(())
+    }
+
+    fn op_pitch(&mut self, inst: u16) {
+        let rx = ((inst & 0xf00) >> 8) as usize;
+
+        self.pitch = self.registers[rx];
+    }
+
+    // Post FX55/FX65 adjustment of I
+    fn advance_index(&mut self, rx: usize) {
+        let inc = match self.quirks.load_store_index {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::ByX => rx as u16,
+            IndexIncrement::ByXPlusOne => rx as u16 + 1,
+        };
+        self.i = self.i.wrapping_add(inc);
+    }
+
+    /// Snapshot the complete machine state. Quirks are configuration
+    /// rather than state and are not included.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = StateWriter::new();
+
+        w.u32(self.memory.len() as u32);
+        w.bytes(&self.memory);
+        w.bytes(&self.registers);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.u8(self.sp);
+        w.u16(self.i);
+        w.u16(self.pc);
+        w.u8(self.dt);
+        w.u8(self.st);
+        for key in self.keys.iter() {
+            w.bool(*key);
+        }
+
+        match self.key_wait {
+            Some(wait) => {
+                w.bool(true);
+                w.u8(wait.reg as u8);
+                w.bool(wait.pressed.is_some());
+                w.u8(wait.pressed.unwrap_or(0) as u8);
+            },
+            None => w.bool(false),
+        }
+
+        w.bool(self.hires);
+        w.u8(self.planes);
+        w.bytes(&self.frame_buffer);
+        w.bytes(&self.rpl);
+        w.bool(self.exited);
+        w.bool(self.audio_pattern.is_some());
+        w.bytes(&self.audio_pattern.unwrap_or([0u8; AUDIO_PATTERN_LEN]));
+        w.u8(self.pitch);
+
+        w.finish(self.machine.id())
+    }
+
+    /// Restore a snapshot taken by `save_state`. The core is left
+    /// untouched if the state is invalid or from another machine type.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = StateReader::new(data, self.machine.id())?;
+
+        let mem_len = r.u32()? as usize;
+        if mem_len != self.memory.len() {
+            return Err(StateError::Corrupt);
+        }
+        let memory = r.bytes(mem_len)?;
+
+        let mut registers = [0u8; 0x10];
+        registers.copy_from_slice(r.bytes(0x10)?);
+        let mut stack = [0u16; 0x10];
+        for addr in stack.iter_mut() {
+            *addr = r.u16()?;
+        }
+        let sp = r.u8()?;
+        if sp as usize > stack.len() {
+            return Err(StateError::Corrupt);
+        }
+        let i = r.u16()?;
+        let pc = r.u16()?;
+        let dt = r.u8()?;
+        let st = r.u8()?;
+        // The keypad is host input rather than machine state, so the keys
+        // held when the state was saved are skipped in favor of the ones
+        // held now
+        for _ in 0..self.keys.len() {
+            r.bool()?;
+        }
+
+        let key_wait = if r.bool()? {
+            let reg = r.u8()? as usize;
+            let has_pressed = r.bool()?;
+            let pressed = r.u8()? as usize;
+            if reg >= registers.len() || pressed >= self.keys.len() {
+                return Err(StateError::Corrupt);
+            }
+            // A key that's no longer held will never be released, so the
+            // wait starts over
+            let held = has_pressed && self.keys[pressed];
+            Some(KeyWait { reg, pressed: if held { Some(pressed) } else { None } })
+        } else {
+            None
+        };
+
+        let hires = r.bool()?;
+        let planes = r.u8()?;
+        let mut frame_buffer = [0u8; HIRES_SCREEN_X * HIRES_SCREEN_Y];
+        frame_buffer.copy_from_slice(r.bytes(HIRES_SCREEN_X * HIRES_SCREEN_Y)?);
+        let mut rpl = [0u8; 0x10];
+        rpl.copy_from_slice(r.bytes(0x10)?);
+        let exited = r.bool()?;
+        let has_pattern = r.bool()?;
+        let mut pattern = [0u8; AUDIO_PATTERN_LEN];
+        pattern.copy_from_slice(r.bytes(AUDIO_PATTERN_LEN)?);
+        let pitch = r.u8()?;
+        r.finish()?;
+
+        self.memory.copy_from_slice(memory);
+        self.registers = registers;
+        self.stack = stack;
+        self.sp = sp;
+        self.i = i;
+        self.pc = pc;
+        self.dt = dt;
+        self.st = st;
+        self.key_wait = key_wait;
+        self.hires = hires;
+        self.planes = planes;
+        self.frame_buffer = frame_buffer;
+        self.rpl = rpl;
+        self.exited = exited;
+        self.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        self.pitch = pitch;
+        Ok(())
     }
 
     pub fn soft_reset(&mut self) {
-        println!("Performing soft reset");
-        self.pc = 0x200;
+        debug!("soft reset");
+        self.pc = self.start;
         self.i = 0x00;
         self.sp = 0x00;
+        self.key_wait = None;
+        self.exited = false;
+        self.hires = false;
+        self.planes = 0x1;
+        self.audio_pattern = None;
+        self.pitch = 64;
 
         // Clear the framebuffer
         self.op_cls(0x00);
     }
 
-    fn trap(inst: u16) {
-        println!("ERROR: Unable to process opcode: {:x}", inst);
-        loop {}
-    }
-
-    fn execute(&mut self, inst: u16) {
-        let n0 = (inst & 0xf) as u8;
-        let n1 = (inst >> 4) as u8 & 0xf;
-        let n2 = (inst >> 8) as u8 & 0xf;
-        let n3 = (inst >> 12) as u8 & 0xf;
-
-        match (n3, n2, n1, n0) {
-            (0x0,   _, 0xE, 0x0) => self.op_cls(inst),
-            (0x0,   _, 0xE, 0xE) => self.op_ret(inst),
-            (0x1,   _,   _,   _) => self.op_jp(inst),
-            (0x2,   _,   _,   _) => self.op_call(inst),
-            (0x3,   _,   _,   _) => self.op_se(inst),
-            (0x4,   _,   _,   _) => self.op_sne(inst),
-            (0x5,   _,   _,   _) => self.op_sereg(inst),
-            (0x6,   _,   _,   _) => self.op_ld(inst),
-            (0x7,   _,   _,   _) => self.op_add(inst),
-            (0x8,   _,   _, 0x0) => self.op_ldreg(inst),
-            (0x8,   _,   _, 0x1) => self.op_or(inst),
-            (0x8,   _,   _, 0x2) => self.op_and(inst),
-            (0x8,   _,   _, 0x3) => self.op_xor(inst),
-            (0x8,   _,   _, 0x4) => self.op_addcarry(inst),
-            (0x8,   _,   _, 0x5) => self.op_sub(inst),
-            (0x8,   _,   _, 0x6) => self.op_shr(inst),
-            (0x8,   _,   _, 0x7) => self.op_subn(inst),
-            (0x8,   _,   _, 0xE) => self.op_shl(inst),
-            (0x9,   _,   _, 0x0) => self.op_snereg(inst),
-            (0xA,   _,   _,   _) => self.op_ldi(inst),
-            (0xB,   _,   _,   _) => self.op_jp_offset(inst),
-            (0xC,   _,   _,   _) => self.op_rnd(inst),
-            (0xD,   _,   _,   _) => self.op_drw(inst),
-            (0xE,   _, 0x9, 0xE) => self.op_skp(inst),
-            (0xE,   _, 0xA, 0x1) => self.op_sknp(inst),
-            (0xF,   _, 0x0, 0x7) => self.op_ldreg_dt(inst),
-            (0xF,   _, 0x0, 0xA) => self.op_ldreg_key(inst),
-            (0xF,   _, 0x1, 0x5) => self.op_lddt_reg(inst),
-            (0xF,   _, 0x1, 0x8) => self.op_ldst_reg(inst),
-            (0xF,   _, 0x1, 0xE) => self.op_addi_reg(inst),
-            (0xF,   _, 0x2, 0x9) => self.op_ldf(inst),
-            (0xF,   _, 0x3, 0x3) => self.op_ldb(inst),
-            (0xF,   _, 0x5, 0x5) => self.op_ldreg_mem(inst),
-            (0xF,   _, 0x6, 0x5) => self.op_ldmem_reg(inst),
-            (_,_,_,_) => Core::trap(inst),
-        }
-    }
-
-    fn fetch(&mut self) -> u16 {
-        let result: u16 = (self.memory[self.pc as usize] as u16) << 8 | self.memory[self.pc as usize + 1] as u16;
-        print!("Fetch[0x{:04x}]: 0x{:04x} | ",  self.pc, result);
-
-        self.pc += 2;
-        result
+    // Decoding is left to the disassembler so listings, the debugger and
+    // the core can't disagree about what an opcode is. All that's decided
+    // here is whether this machine has it.
+    fn execute(&mut self, inst: u16) -> Result<(), CoreError> {
+        use disasm::Instruction::*;
+
+        let schip = self.machine.has_schip();
+        let xochip = self.machine.has_xochip();
+
+        match disasm::decode(inst) {
+            Scd(_) if schip => self.op_scd(inst),
+            Scu(_) if xochip => self.op_scu(inst),
+            Cls => self.op_cls(inst),
+            Ret => self.op_ret(inst)?,
+            Scr if schip => self.op_scr(inst),
+            Scl if schip => self.op_scl(inst),
+            Exit if schip => self.op_exit(inst),
+            Low if schip => self.op_low(inst),
+            High if schip => self.op_high(inst),
+            Jp(_) => self.op_jp(inst),
+            Call(_) => self.op_call(inst)?,
+            SeByte(..) => self.op_se(inst)?,
+            SneByte(..) => self.op_sne(inst)?,
+            SeReg(..) => self.op_sereg(inst)?,
+            LdRangeMem(..) if xochip => self.op_ldrange_mem(inst)?,
+            LdMemRange(..) if xochip => self.op_ldmem_range(inst)?,
+            LdByte(..) => self.op_ld(inst),
+            AddByte(..) => self.op_add(inst),
+            LdReg(..) => self.op_ldreg(inst),
+            Or(..) => self.op_or(inst),
+            And(..) => self.op_and(inst),
+            Xor(..) => self.op_xor(inst),
+            AddReg(..) => self.op_addcarry(inst),
+            Sub(..) => self.op_sub(inst),
+            Shr(..) => self.op_shr(inst),
+            Subn(..) => self.op_subn(inst),
+            Shl(..) => self.op_shl(inst),
+            SneReg(..) => self.op_snereg(inst)?,
+            LdI(_) => self.op_ldi(inst),
+            JpOffset(_) => self.op_jp_offset(inst),
+            Rnd(..) => self.op_rnd(inst),
+            Drw(..) => self.op_drw(inst)?,
+            Skp(_) => self.op_skp(inst)?,
+            Sknp(_) => self.op_sknp(inst)?,
+            LdILong(_) if xochip => self.op_ldi_long(inst)?,
+            Plane(_) if xochip => self.op_plane(inst),
+            Audio if xochip => self.op_audio(inst)?,
+            LdRegDt(_) => self.op_ldreg_dt(inst),
+            LdRegKey(_) => self.op_ldreg_key(inst),
+            LdDtReg(_) => self.op_lddt_reg(inst),
+            LdStReg(_) => self.op_ldst_reg(inst),
+            AddIReg(_) => self.op_addi_reg(inst),
+            LdF(_) => self.op_ldf(inst),
+            LdHf(_) if schip => self.op_ldhf(inst),
+            LdB(_) => self.op_ldb(inst)?,
+            Pitch(_) if xochip => self.op_pitch(inst),
+            LdMemReg(_) => self.op_ldreg_mem(inst)?,
+            LdRegMem(_) => self.op_ldmem_reg(inst)?,
+            LdRReg(_) if schip => self.op_ldr_reg(inst),
+            LdRegR(_) if schip => self.op_ldreg_r(inst),
+            // SYS calls into machine code can't be emulated
+            _ => return Err(CoreError::UnknownOpcode {
+                pc: self.cur_pc(),
+                opcode: inst
+            }),
+        }
+        Ok(())
     }
 
-    pub fn tick(&mut self) {
-        let inst = self.fetch();
-        self.execute(inst);
+    fn fetch(&mut self) -> Result<u16, CoreError> {
+        let pc = self.pc as usize;
+        if pc + 1 >= self.memory.len() {
+            return Err(CoreError::PcOutOfRange(self.pc));
+        }
+
+        let result: u16 = (self.memory[pc] as u16) << 8 | self.memory[pc + 1] as u16;
+        self.pc = self.pc.wrapping_add(2);
+        Ok(result)
+    }
+
+    /// Execute a single instruction. Timers are not touched here, see
+    /// `tick_timers`.
+    pub fn tick(&mut self) -> Result<(), CoreError> {
+        self.accesses.clear();
+        if self.key_wait.is_some() || self.exited {
+            return Ok(());
+        }
+
+        let before = self.registers();
+        let inst = self.fetch()?;
+        trace!("0x{:04x}: 0x{:04x} {}", before.pc, inst, disasm::decode(inst));
+        self.execute(inst)?;
+        self.cycles += 1;
+
+        // Taken out while called so the tracer can look at the core
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.instruction(self, &before, inst);
+            self.tracer = Some(tracer);
+        }
+        Ok(())
+    }
+
+    /// Execute `opcode` as though it had just been fetched from PC, without
+    /// reading it from memory. Lets tests run a single instruction against
+    /// a core set up with `set_registers` and `write_memory`.
+    pub fn execute_opcode(&mut self, opcode: u16) -> Result<(), CoreError> {
+        self.accesses.clear();
+        self.pc = self.pc.wrapping_add(2);
+        self.execute(opcode)
+    }
+
+    /// Number of instructions executed since the core was created
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Install a tracer to be called after every instruction executed,
+    /// or remove it with None.
+    pub fn set_tracer(&mut self, tracer: Option<Box<dyn Tracer>>) {
+        self.tracer = tracer;
+    }
+
+    /// Replace the source of CXNN's random numbers
+    pub fn set_random(&mut self, random: Box<dyn Random>) {
+        self.random = random;
+    }
+
+    /// Count the delay and sound timers down by one. Must be called at
+    /// `TIMER_FREQ` regardless of how fast instructions are executed.
+    pub fn tick_timers(&mut self) {
         if self.dt > 0 {
             self.dt -= 1;
         }
+        if self.st > 0 {
+            self.st -= 1;
+        }
+    }
+
+    /// Run one 60 Hz frame: `cycles_per_frame` instructions followed by a
+    /// single timer tick.
+    pub fn run_frame(&mut self, cycles_per_frame: u32) -> Result<(), CoreError> {
+        for _ in 0..cycles_per_frame {
+            self.tick()?;
+        }
+        self.tick_timers();
+        Ok(())
     }
 }
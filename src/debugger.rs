@@ -0,0 +1,680 @@
+use chip8::{Core, CoreError};
+use disasm;
+use expr::{self, Expr};
+use headless;
+use std::fmt;
+use std::io::{self, Write};
+
+const DISASM_LINES: usize = 10;
+const MEM_BYTES: usize = 64;
+
+enum Trigger {
+    // Before executing the instruction at an address
+    Addr(u16),
+    // Before executing an instruction matching a pattern such as DXYN
+    Opcode { mask: u16, value: u16 },
+    // Before executing an instruction with the given mnemonic
+    Mnemonic(String),
+    // Before every instruction, leaving it all to the condition
+    Always,
+    // After an instruction reads and/or writes memory in start..end
+    Watch { start: usize, end: usize, read: bool, write: bool },
+}
+
+struct Breakpoint {
+    id: usize,
+    trigger: Trigger,
+    cond: Option<(String, Expr)>,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.id)?;
+        match self.trigger {
+            Trigger::Addr(addr) => write!(f, "break at 0x{:04X}", addr)?,
+            Trigger::Opcode { mask, value } => {
+                let pattern: String = (0..4).rev()
+                    .map(|nibble| match (mask >> (nibble * 4)) & 0xf {
+                        0 => '?',
+                        _ => format!("{:X}", (value >> (nibble * 4)) & 0xf).chars().next().unwrap(),
+                    })
+                    .collect();
+                write!(f, "break on {}", pattern)?
+            },
+            Trigger::Mnemonic(ref name) => write!(f, "break on {}", name)?,
+            Trigger::Always => write!(f, "break")?,
+            Trigger::Watch { start, end, read, write } => {
+                let kind = match (read, write) {
+                    (true, true) => "access",
+                    (true, false) => "read",
+                    _ => "write",
+                };
+                write!(f, "watch {} 0x{:04X}-0x{:04X}", kind, start, end - 1)?
+            },
+        }
+        if let Some((ref text, _)) = self.cond {
+            write!(f, " if {}", text)?;
+        }
+        Ok(())
+    }
+}
+
+/// Takes over running a core so it can be stopped and inspected, either
+/// from the command line (`Debugger`) or from GDB (`gdb::GdbStub`)
+pub trait Monitor {
+    /// True while execution is stopped
+    fn paused(&self) -> bool;
+
+    /// Stop at the next opportunity
+    fn pause(&mut self);
+
+    /// Run up to the end of the current frame, pausing early at a
+    /// breakpoint, watchpoint or fault
+    fn run_frame(&mut self, core: &mut Core) -> Result<(), CoreError>;
+
+    /// Let the user inspect and modify the paused core until execution is
+    /// resumed. Returns false if the user asked to quit.
+    fn interact(&mut self, core: &mut Core) -> bool;
+}
+
+/// Command line debugger driving a core an instruction at a time so it can
+/// stop at breakpoints. Timers still tick once every `cycles_per_frame`
+/// instructions, however execution is split up.
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    next_id: usize,
+    cycles_per_frame: u32,
+    cycle: u32,
+    paused: bool,
+    // Skip breakpoints on the first instruction when resuming, so
+    // execution can leave the one it stopped at
+    resuming: bool,
+    last_command: String,
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    let lower = text.to_lowercase();
+    match lower.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => lower.parse().ok(),
+    }
+}
+
+fn parse_addr(text: Option<&&str>) -> Result<u16, String> {
+    match text {
+        Some(text) => match parse_number(text) {
+            Some(addr) if addr <= 0xffff => Ok(addr as u16),
+            _ => Err(format!("invalid address '{}'", text)),
+        },
+        None => Err("missing address".to_string()),
+    }
+}
+
+// Parse an instruction pattern such as DXYN or F?55, where X, Y, N, K and
+// ? match any nibble
+fn parse_opcode(text: &str) -> Option<(u16, u16)> {
+    if text.len() != 4 {
+        return None;
+    }
+
+    let mut mask = 0;
+    let mut value = 0;
+    for c in text.chars() {
+        mask <<= 4;
+        value <<= 4;
+        match c.to_digit(16) {
+            Some(digit) => {
+                mask |= 0xf;
+                value |= digit as u16;
+            },
+            None if "XYNKxynk?".contains(c) => {},
+            None => return None,
+        }
+    }
+    Some((mask, value))
+}
+
+fn print_help() {
+    println!("Commands:");
+    println!("    step [n]            Execute n instructions (default 1)");
+    println!("    continue            Resume execution");
+    println!("    break <addr>        Stop before executing the instruction at addr");
+    println!("    break op <opcode>   Stop before any matching instruction, given as");
+    println!("                        a pattern such as DXYN or a mnemonic like DRW");
+    println!("    watch <addr> [len]  Stop after an instruction writes to memory");
+    println!("    rwatch <addr> [len] Stop after an instruction reads memory");
+    println!("    awatch <addr> [len] Stop after an instruction reads or writes memory");
+    println!("                        Breakpoints and watchpoints take an optional");
+    println!("                        'if <expr>' condition, such as");
+    println!("                        'break if V0 == 5 && I > 0x300'");
+    println!("    info                List breakpoints and watchpoints");
+    println!("    delete [n]          Remove one or all breakpoints and watchpoints");
+    println!("    regs                Show the registers");
+    println!("    mem <addr> [len]    Dump memory");
+    println!("    stack               Show the call stack");
+    println!("    disasm [addr]       Disassemble from addr (default pc)");
+    println!("    set <reg> <value>   Set V0-VF, I, PC, SP, DT or ST");
+    println!("    frame               Print the display");
+    println!("    quit                Exit the emulator");
+    println!("An empty line repeats the last command.");
+}
+
+impl Debugger {
+    /// Create a debugger, paused before the first instruction
+    pub fn new(cycles_per_frame: u32) -> Debugger {
+        Debugger {
+            breakpoints: Vec::new(),
+            next_id: 1,
+            cycles_per_frame,
+            cycle: 0,
+            paused: true,
+            resuming: false,
+            last_command: String::new(),
+        }
+    }
+
+    // Execute a single instruction, ticking the timers at frame boundaries
+    fn step(&mut self, core: &mut Core) -> Result<(), CoreError> {
+        core.tick()?;
+        self.cycle += 1;
+        if self.cycle >= self.cycles_per_frame {
+            self.cycle = 0;
+            core.tick_timers();
+        }
+        Ok(())
+    }
+
+    fn condition_met(&self, bp: &Breakpoint, core: &Core) -> bool {
+        bp.cond.as_ref().map_or(true, |cond| cond.1.eval(core) != 0)
+    }
+
+    // The breakpoint, if any, that stops execution before the next
+    // instruction
+    fn break_before(&self, core: &Core) -> Option<&Breakpoint> {
+        let pc = core.registers().pc as usize;
+        let decoded = disasm::decode_at(core.memory(), pc);
+        let word = match core.memory().get(pc..pc + 2) {
+            Some(bytes) => (bytes[0] as u16) << 8 | bytes[1] as u16,
+            None => return None,
+        };
+
+        self.breakpoints.iter().find(|bp| {
+            let hit = match bp.trigger {
+                Trigger::Addr(addr) => addr as usize == pc,
+                Trigger::Opcode { mask, value } => word & mask == value,
+                Trigger::Mnemonic(ref name) => decoded.is_some_and(|(inst, _)| {
+                    inst.to_string().split_whitespace().next() == Some(name.as_str())
+                }),
+                Trigger::Always => true,
+                Trigger::Watch { .. } => false,
+            };
+            hit && self.condition_met(bp, core)
+        })
+    }
+
+    // The watchpoint, if any, hit by the memory accesses of the last
+    // instruction, along with the access that hit it
+    fn watch_hit(&self, core: &Core) -> Option<(&Breakpoint, usize, bool)> {
+        for access in core.accesses() {
+            for bp in &self.breakpoints {
+                if let Trigger::Watch { start, end, read, write } = bp.trigger {
+                    let kind = if access.write { write } else { read };
+                    if kind && access.addr >= start && access.addr < end
+                        && self.condition_met(bp, core)
+                    {
+                        return Some((bp, access.addr, access.write));
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    // Execute an instruction, then check watchpoints. Returns true if one
+    // was hit.
+    fn step_watched(&mut self, core: &mut Core) -> Result<bool, CoreError> {
+        let pc = core.registers().pc;
+        self.step(core)?;
+
+        match self.watch_hit(core) {
+            Some((bp, addr, write)) => {
+                println!("Watchpoint {}: {} 0x{:04X} by the instruction at 0x{:04X}",
+                         bp.id, if write { "write to" } else { "read from" }, addr, pc);
+                Ok(true)
+            },
+            None => Ok(false),
+        }
+    }
+
+    fn add(&mut self, trigger: Trigger, cond: Option<(String, Expr)>) {
+        let bp = Breakpoint { id: self.next_id, trigger, cond };
+        println!("Added {}", bp);
+        self.next_id += 1;
+        self.breakpoints.push(bp);
+    }
+
+    // Run a single command, returning false to quit
+    fn command(&mut self, core: &mut Core, line: &str) -> Result<bool, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args = if words.is_empty() { &words[..] } else { &words[1..] };
+
+        match words.first().cloned().unwrap_or("") {
+            "" => {},
+            "step" | "s" => {
+                let count = match args.first() {
+                    Some(n) => parse_number(n).ok_or(format!("invalid count '{}'", n))?,
+                    None => 1,
+                };
+                let watching = self.breakpoints.iter().any(|bp| matches!(bp.trigger, Trigger::Watch { .. }));
+                core.observe_memory(watching);
+
+                for n in 0..count {
+                    if n > 0 {
+                        if let Some(bp) = self.break_before(core) {
+                            println!("Breakpoint {} at 0x{:04X}", bp.id, core.registers().pc);
+                            break;
+                        }
+                    }
+                    match self.step_watched(core) {
+                        Ok(false) => {},
+                        Ok(true) => break,
+                        Err(e) => {
+                            println!("CORE FAULT: {}", e);
+                            break;
+                        },
+                    }
+                }
+                self.print_location(core);
+            },
+            "continue" | "c" => {
+                self.resuming = true;
+                self.paused = false;
+            },
+            "break" | "b" | "watch" | "rwatch" | "awatch" => {
+                // Split off any condition
+                let (args, cond) = match args.iter().position(|arg| *arg == "if") {
+                    Some(idx) => {
+                        let text = args[idx + 1..].join(" ");
+                        let cond = expr::parse(&text)?;
+                        (&args[..idx], Some((text, cond)))
+                    },
+                    None => (args, None),
+                };
+
+                let trigger = match (words[0], args) {
+                    ("break", []) | ("b", []) if cond.is_some() => Trigger::Always,
+                    ("break", ["op", op]) | ("b", ["op", op]) => match parse_opcode(op) {
+                        Some((mask, value)) => Trigger::Opcode { mask, value },
+                        None => Trigger::Mnemonic(op.to_uppercase()),
+                    },
+                    ("break", [_]) | ("b", [_]) => Trigger::Addr(parse_addr(args.first())?),
+                    (kind, [_]) | (kind, [_, _]) if kind.ends_with("watch") => {
+                        let start = parse_addr(args.first())? as usize;
+                        let len = match args.get(1) {
+                            Some(n) => parse_number(n).ok_or(format!("invalid length '{}'", n))? as usize,
+                            None => 1,
+                        };
+                        Trigger::Watch {
+                            start,
+                            end: start + len.max(1),
+                            read: kind != "watch",
+                            write: kind != "rwatch",
+                        }
+                    },
+                    _ => return Err("invalid breakpoint, try 'help'".to_string()),
+                };
+                self.add(trigger, cond);
+            },
+            "info" | "i" => {
+                if self.breakpoints.is_empty() {
+                    println!("No breakpoints or watchpoints");
+                }
+                for bp in &self.breakpoints {
+                    println!("{}", bp);
+                }
+            },
+            "delete" | "d" => {
+                match args.first() {
+                    Some(id) => {
+                        let id = parse_number(id).ok_or(format!("invalid breakpoint '{}'", id))? as usize;
+                        match self.breakpoints.iter().position(|bp| bp.id == id) {
+                            Some(idx) => self.breakpoints.remove(idx),
+                            None => return Err(format!("no breakpoint {}", id)),
+                        };
+                        println!("Deleted {}", id);
+                    },
+                    None => {
+                        self.breakpoints.clear();
+                        println!("Deleted all breakpoints and watchpoints");
+                    },
+                }
+            },
+            "regs" | "r" => print!("{}", headless::register_text(core)),
+            "mem" | "m" => {
+                let addr = parse_addr(args.first())? as usize;
+                let len = match args.get(1) {
+                    Some(n) => parse_number(n).ok_or(format!("invalid length '{}'", n))? as usize,
+                    None => MEM_BYTES,
+                };
+                let memory = core.memory();
+                let end = (addr + len).min(memory.len());
+                for row in (addr..end).step_by(16) {
+                    let bytes: Vec<String> = memory[row..(row + 16).min(end)].iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect();
+                    println!("0x{:04X}  {}", row, bytes.join(" "));
+                }
+            },
+            "stack" => {
+                let stack = core.stack();
+                if stack.is_empty() {
+                    println!("Stack is empty");
+                }
+                for (depth, addr) in stack.iter().enumerate().rev() {
+                    println!("#{:<2} 0x{:04X}", depth, addr);
+                }
+            },
+            "disasm" | "x" => {
+                let addr = match args.first() {
+                    Some(_) => parse_addr(args.first())?,
+                    None => core.registers().pc,
+                };
+                self.print_disasm(core, addr as usize, DISASM_LINES);
+            },
+            "set" => {
+                let (reg, value) = match (args.first(), args.get(1)) {
+                    (Some(reg), Some(value)) => (reg.to_uppercase(), value),
+                    _ => return Err("usage: set <reg> <value>".to_string()),
+                };
+                let value = parse_number(value).ok_or(format!("invalid value '{}'", value))?;
+                let mut regs = core.registers();
+                let max = match reg.as_str() {
+                    "I" | "PC" => 0xffff,
+                    _ => 0xff,
+                };
+                if value > max {
+                    return Err(format!("value 0x{:X} does not fit in {}", value, reg));
+                }
+
+                match reg.as_str() {
+                    "I" => regs.i = value as u16,
+                    "PC" => regs.pc = value as u16,
+                    "SP" => regs.sp = value as u8,
+                    "DT" => regs.dt = value as u8,
+                    "ST" => regs.st = value as u8,
+                    v if v.len() == 2 && v.starts_with('V') => match u8::from_str_radix(&v[1..], 16) {
+                        Ok(x) => regs.v[x as usize] = value as u8,
+                        Err(_) => return Err(format!("unknown register '{}'", reg)),
+                    },
+                    _ => return Err(format!("unknown register '{}'", reg)),
+                }
+                core.set_registers(&regs);
+            },
+            "frame" | "f" => print!("{}", headless::screen_text(core)),
+            "help" | "h" | "?" => print_help(),
+            "quit" | "q" => return Ok(false),
+            other => return Err(format!("unknown command '{}', try 'help'", other)),
+        }
+        Ok(true)
+    }
+
+    fn print_disasm(&self, core: &Core, mut addr: usize, lines: usize) {
+        let pc = core.registers().pc as usize;
+        let memory = core.memory();
+
+        for _ in 0..lines {
+            let (inst, len) = match disasm::decode_at(memory, addr) {
+                Some(decoded) => decoded,
+                None => break,
+            };
+            let bp = self.breakpoints.iter().any(|bp| match bp.trigger {
+                Trigger::Addr(bp_addr) => bp_addr as usize == addr,
+                _ => false,
+            });
+            let marker = match (addr == pc, bp) {
+                (true, _) => "=>",
+                (false, true) => " *",
+                (false, false) => "  ",
+            };
+            println!("{} 0x{:04X}  {}", marker, addr, inst);
+            addr += len;
+        }
+    }
+
+    fn print_location(&self, core: &Core) {
+        self.print_disasm(core, core.registers().pc as usize, 1);
+    }
+}
+
+impl Monitor for Debugger {
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    fn run_frame(&mut self, core: &mut Core) -> Result<(), CoreError> {
+        let watching = self.breakpoints.iter().any(|bp| matches!(bp.trigger, Trigger::Watch { .. }));
+        core.observe_memory(watching);
+
+        while !self.paused {
+            if !self.resuming {
+                if let Some(bp) = self.break_before(core) {
+                    println!("Breakpoint {} at 0x{:04X}", bp.id, core.registers().pc);
+                    self.paused = true;
+                    break;
+                }
+            }
+            self.resuming = false;
+
+            match self.step_watched(core) {
+                Ok(true) => self.paused = true,
+                Ok(false) => {},
+                Err(e) => {
+                    self.paused = true;
+                    return Err(e);
+                },
+            }
+            if self.cycle == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn interact(&mut self, core: &mut Core) -> bool {
+        self.print_location(core);
+
+        while self.paused {
+            print!("(chip8r) ");
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            match io::stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => return false,
+                Ok(_) => {},
+            }
+
+            let line = line.trim().to_string();
+            let command = if line.is_empty() { self.last_command.clone() } else { line };
+            self.last_command = command.clone();
+
+            match self.command(core, &command) {
+                Ok(true) => {},
+                Ok(false) => return false,
+                Err(e) => println!("{}", e),
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::Machine;
+    use quirks::Quirks;
+
+    // Stores V0 to 0x300, reads it back, draws, then adds 1 to V0 forever.
+    // The VIP quirks leave I at 0x301 after each load and store.
+    const ROM: [u8; 16] = [
+        0x60, 0x05, // 0x200  LD V0, 0x05
+        0xa3, 0x00, // 0x202  LD I, 0x300
+        0xf0, 0x55, // 0x204  LD [I], V0
+        0xa3, 0x00, // 0x206  LD I, 0x300
+        0xf0, 0x65, // 0x208  LD V0, [I]
+        0xd0, 0x11, // 0x20A  DRW V0, V1, 1
+        0x70, 0x01, // 0x20C  ADD V0, 0x01
+        0x12, 0x0c, // 0x20E  JP 0x20C
+    ];
+
+    fn setup(commands: &[&str]) -> (Debugger, Core) {
+        let mut core = Core::new(Machine::Chip8, Quirks::cosmac_vip());
+        core.load_rom(&ROM).unwrap();
+        let mut debugger = Debugger::new(10);
+        for command in commands {
+            assert_eq!(debugger.command(&mut core, command), Ok(true), "{}", command);
+        }
+        (debugger, core)
+    }
+
+    // Resume and run until something stops execution, giving the pc it
+    // stopped at
+    fn resume(debugger: &mut Debugger, core: &mut Core) -> Option<u16> {
+        debugger.command(core, "continue").unwrap();
+        for _ in 0..10 {
+            debugger.run_frame(core).unwrap();
+            if debugger.paused() {
+                return Some(core.registers().pc);
+            }
+        }
+        None
+    }
+
+    #[test]
+    fn starts_paused() {
+        let (mut debugger, mut core) = setup(&[]);
+        assert!(debugger.paused());
+        debugger.run_frame(&mut core).unwrap();
+        assert_eq!(core.registers().pc, 0x200);
+    }
+
+    #[test]
+    fn address_breakpoint() {
+        let (mut debugger, mut core) = setup(&["break 0x206"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x206));
+        // Leaves the breakpoint it stopped on, which is never reached again
+        assert_eq!(resume(&mut debugger, &mut core), None);
+    }
+
+    #[test]
+    fn opcode_pattern_breakpoints() {
+        let (mut debugger, mut core) = setup(&["break op DXYN"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20a));
+
+        let (mut debugger, mut core) = setup(&["break op f?65"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x208));
+
+        let (mut debugger, mut core) = setup(&["break op 00E0"]);
+        assert_eq!(resume(&mut debugger, &mut core), None);
+    }
+
+    #[test]
+    fn mnemonic_breakpoint() {
+        let (mut debugger, mut core) = setup(&["break op add"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20c));
+        assert_eq!(core.registers().v[0], 5);
+        // Fires each time round the loop
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20c));
+        assert_eq!(core.registers().v[0], 6);
+    }
+
+    #[test]
+    fn conditional_breakpoints() {
+        let (mut debugger, mut core) = setup(&["break if V0 == 7"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20e));
+        assert_eq!(core.registers().v[0], 7);
+
+        let (mut debugger, mut core) = setup(&["break 0x20C if V0 == 8"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20c));
+        assert_eq!(core.registers().v[0], 8);
+
+        let (mut debugger, mut core) = setup(&["break 0x206 if [0x300] != 5"]);
+        assert_eq!(resume(&mut debugger, &mut core), None);
+    }
+
+    #[test]
+    fn watchpoints() {
+        // Stops after the instruction that made the access
+        let (mut debugger, mut core) = setup(&["watch 0x300"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x206));
+        assert_eq!(resume(&mut debugger, &mut core), None);
+
+        let (mut debugger, mut core) = setup(&["rwatch 0x300 2"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20a));
+        // DRW reads the sprite at I
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x20c));
+        assert_eq!(resume(&mut debugger, &mut core), None);
+
+        let (mut debugger, mut core) = setup(&["awatch 0x2FE 2"]);
+        assert_eq!(resume(&mut debugger, &mut core), None);
+
+        let (mut debugger, mut core) = setup(&["awatch 0x2FF 2 if V0 == 5"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x206));
+    }
+
+    #[test]
+    fn step_stops_at_breakpoints() {
+        let (mut debugger, mut core) = setup(&["break 0x20A", "step 10"]);
+        assert_eq!(core.registers().pc, 0x20a);
+        debugger.command(&mut core, "step").unwrap();
+        assert_eq!(core.registers().pc, 0x20c);
+    }
+
+    #[test]
+    fn delete() {
+        let (mut debugger, mut core) = setup(&["break 0x206", "watch 0x300", "delete 1"]);
+        assert_eq!(resume(&mut debugger, &mut core), Some(0x206));
+        assert_eq!(debugger.command(&mut core, "delete 1"), Err("no breakpoint 1".to_string()));
+        debugger.command(&mut core, "delete").unwrap();
+        assert!(debugger.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn set_registers() {
+        let (_, core) = setup(&["set v3 0x12", "set I 0x345", "set pc 0x20A", "set DT 9"]);
+        let regs = core.registers();
+        assert_eq!((regs.v[3], regs.i, regs.pc, regs.dt), (0x12, 0x345, 0x20a, 9));
+    }
+
+    #[test]
+    fn command_errors() {
+        let (mut debugger, mut core) = setup(&[]);
+        let mut error = |line: &str| debugger.command(&mut core, line).unwrap_err();
+
+        assert_eq!(error("break"), "invalid breakpoint, try 'help'");
+        assert_eq!(error("break 0x10000"), "invalid address '0x10000'");
+        assert_eq!(error("break op"), "invalid address 'op'");
+        assert_eq!(error("break if V0 =="), "unexpected end of expression");
+        assert_eq!(error("watch"), "invalid breakpoint, try 'help'");
+        assert_eq!(error("watch 0x300 many"), "invalid length 'many'");
+        assert_eq!(error("step x"), "invalid count 'x'");
+        assert_eq!(error("set V0 0x100"), "value 0x100 does not fit in V0");
+        assert_eq!(error("set VG 1"), "unknown register 'VG'");
+        assert_eq!(error("set V0"), "usage: set <reg> <value>");
+        assert_eq!(error("frobnicate"), "unknown command 'frobnicate', try 'help'");
+        assert_eq!(debugger.command(&mut core, "quit"), Ok(false));
+        assert!(debugger.breakpoints.is_empty());
+    }
+
+    #[test]
+    fn opcode_patterns() {
+        assert_eq!(parse_opcode("DXYN"), Some((0xf000, 0xd000)));
+        assert_eq!(parse_opcode("F?55"), Some((0xf0ff, 0xf055)));
+        assert_eq!(parse_opcode("00e0"), Some((0xffff, 0x00e0)));
+        assert_eq!(parse_opcode("DRW"), None);
+        assert_eq!(parse_opcode("D?Z0"), None);
+    }
+}
@@ -0,0 +1,598 @@
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A decoded instruction along with its operands. Register operands are
+/// register numbers (0x0 - 0xF).
+///
+/// This covers the base CHIP-8 set along with the SUPER-CHIP and XO-CHIP
+/// extensions. Anything else decodes to `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    Sys(u16),
+    Cls,
+    Ret,
+    Jp(u16),
+    Call(u16),
+    SeByte(u8, u8),
+    SneByte(u8, u8),
+    SeReg(u8, u8),
+    LdByte(u8, u8),
+    AddByte(u8, u8),
+    LdReg(u8, u8),
+    Or(u8, u8),
+    And(u8, u8),
+    Xor(u8, u8),
+    AddReg(u8, u8),
+    Sub(u8, u8),
+    Shr(u8, u8),
+    Subn(u8, u8),
+    Shl(u8, u8),
+    SneReg(u8, u8),
+    LdI(u16),
+    JpOffset(u16),
+    Rnd(u8, u8),
+    Drw(u8, u8, u8),
+    Skp(u8),
+    Sknp(u8),
+    LdRegDt(u8),
+    LdRegKey(u8),
+    LdDtReg(u8),
+    LdStReg(u8),
+    AddIReg(u8),
+    LdF(u8),
+    LdB(u8),
+    LdMemReg(u8),
+    LdRegMem(u8),
+    // SUPER-CHIP
+    Scd(u8),
+    Scr,
+    Scl,
+    Exit,
+    Low,
+    High,
+    LdHf(u8),
+    LdRReg(u8),
+    LdRegR(u8),
+    // XO-CHIP
+    Scu(u8),
+    LdRangeMem(u8, u8),
+    LdMemRange(u8, u8),
+    LdILong(u16),
+    Plane(u8),
+    Audio,
+    Pitch(u8),
+    Unknown(u16),
+}
+
+/// Decode a single instruction word. F000 NNNN is the only instruction
+/// longer than one word, its address comes back as 0 here, use
+/// `decode_at` to pick it up from the following word.
+pub fn decode(inst: u16) -> Instruction {
+    use self::Instruction::*;
+
+    let x = ((inst >> 8) & 0xf) as u8;
+    let y = ((inst >> 4) & 0xf) as u8;
+    let n = (inst & 0xf) as u8;
+    let kk = (inst & 0xff) as u8;
+    let nnn = inst & 0xfff;
+
+    match (inst >> 12, x, y, n) {
+        (0x0, 0x0, 0xC, _) => Scd(n),
+        (0x0, 0x0, 0xD, _) => Scu(n),
+        (0x0, 0x0, 0xE, 0x0) => Cls,
+        (0x0, 0x0, 0xE, 0xE) => Ret,
+        (0x0, 0x0, 0xF, 0xB) => Scr,
+        (0x0, 0x0, 0xF, 0xC) => Scl,
+        (0x0, 0x0, 0xF, 0xD) => Exit,
+        (0x0, 0x0, 0xF, 0xE) => Low,
+        (0x0, 0x0, 0xF, 0xF) => High,
+        (0x0, _, _, _) => Sys(nnn),
+        (0x1, _, _, _) => Jp(nnn),
+        (0x2, _, _, _) => Call(nnn),
+        (0x3, _, _, _) => SeByte(x, kk),
+        (0x4, _, _, _) => SneByte(x, kk),
+        (0x5, _, _, 0x0) => SeReg(x, y),
+        (0x5, _, _, 0x2) => LdRangeMem(x, y),
+        (0x5, _, _, 0x3) => LdMemRange(x, y),
+        (0x6, _, _, _) => LdByte(x, kk),
+        (0x7, _, _, _) => AddByte(x, kk),
+        (0x8, _, _, 0x0) => LdReg(x, y),
+        (0x8, _, _, 0x1) => Or(x, y),
+        (0x8, _, _, 0x2) => And(x, y),
+        (0x8, _, _, 0x3) => Xor(x, y),
+        (0x8, _, _, 0x4) => AddReg(x, y),
+        (0x8, _, _, 0x5) => Sub(x, y),
+        (0x8, _, _, 0x6) => Shr(x, y),
+        (0x8, _, _, 0x7) => Subn(x, y),
+        (0x8, _, _, 0xE) => Shl(x, y),
+        (0x9, _, _, 0x0) => SneReg(x, y),
+        (0xA, _, _, _) => LdI(nnn),
+        (0xB, _, _, _) => JpOffset(nnn),
+        (0xC, _, _, _) => Rnd(x, kk),
+        (0xD, _, _, _) => Drw(x, y, n),
+        (0xE, _, 0x9, 0xE) => Skp(x),
+        (0xE, _, 0xA, 0x1) => Sknp(x),
+        (0xF, 0x0, 0x0, 0x0) => LdILong(0),
+        (0xF, _, 0x0, 0x1) => Plane(x),
+        (0xF, 0x0, 0x0, 0x2) => Audio,
+        (0xF, _, 0x0, 0x7) => LdRegDt(x),
+        (0xF, _, 0x0, 0xA) => LdRegKey(x),
+        (0xF, _, 0x1, 0x5) => LdDtReg(x),
+        (0xF, _, 0x1, 0x8) => LdStReg(x),
+        (0xF, _, 0x1, 0xE) => AddIReg(x),
+        (0xF, _, 0x2, 0x9) => LdF(x),
+        (0xF, _, 0x3, 0x0) => LdHf(x),
+        (0xF, _, 0x3, 0x3) => LdB(x),
+        (0xF, _, 0x3, 0xA) => Pitch(x),
+        (0xF, _, 0x5, 0x5) => LdMemReg(x),
+        (0xF, _, 0x6, 0x5) => LdRegMem(x),
+        (0xF, _, 0x7, 0x5) => LdRReg(x),
+        (0xF, _, 0x8, 0x5) => LdRegR(x),
+        _ => Unknown(inst),
+    }
+}
+
+/// Decode the instruction at `addr`, returning it along with its length in
+/// bytes. Returns None if there isn't a whole instruction left.
+pub fn decode_at(mem: &[u8], addr: usize) -> Option<(Instruction, usize)> {
+    let word = |at: usize| -> Option<u16> {
+        match (mem.get(at), mem.get(at + 1)) {
+            (Some(hi), Some(lo)) => Some((*hi as u16) << 8 | *lo as u16),
+            _ => None,
+        }
+    };
+
+    match decode(word(addr)?) {
+        Instruction::LdILong(_) => match word(addr + 2) {
+            Some(nnnn) => Some((Instruction::LdILong(nnnn), 4)),
+            None => Some((Instruction::Unknown(0xf000), 2)),
+        },
+        inst => Some((inst, 2)),
+    }
+}
+
+impl Instruction {
+    /// Encode back into big endian bytes, the inverse of `decode_at`
+    pub fn encode(&self) -> Vec<u8> {
+        use self::Instruction::*;
+
+        let xy = |op: u16, x: u8, y: u8, n: u16| op << 12 | (x as u16) << 8 | (y as u16) << 4 | n;
+        let xkk = |op: u16, x: u8, kk: u8| op << 12 | (x as u16) << 8 | kk as u16;
+
+        let word = match *self {
+            Sys(addr) => addr & 0xfff,
+            Cls => 0x00e0,
+            Ret => 0x00ee,
+            Jp(addr) => 0x1000 | (addr & 0xfff),
+            Call(addr) => 0x2000 | (addr & 0xfff),
+            SeByte(x, kk) => xkk(0x3, x, kk),
+            SneByte(x, kk) => xkk(0x4, x, kk),
+            SeReg(x, y) => xy(0x5, x, y, 0x0),
+            LdByte(x, kk) => xkk(0x6, x, kk),
+            AddByte(x, kk) => xkk(0x7, x, kk),
+            LdReg(x, y) => xy(0x8, x, y, 0x0),
+            Or(x, y) => xy(0x8, x, y, 0x1),
+            And(x, y) => xy(0x8, x, y, 0x2),
+            Xor(x, y) => xy(0x8, x, y, 0x3),
+            AddReg(x, y) => xy(0x8, x, y, 0x4),
+            Sub(x, y) => xy(0x8, x, y, 0x5),
+            Shr(x, y) => xy(0x8, x, y, 0x6),
+            Subn(x, y) => xy(0x8, x, y, 0x7),
+            Shl(x, y) => xy(0x8, x, y, 0xe),
+            SneReg(x, y) => xy(0x9, x, y, 0x0),
+            LdI(addr) => 0xa000 | (addr & 0xfff),
+            JpOffset(addr) => 0xb000 | (addr & 0xfff),
+            Rnd(x, kk) => xkk(0xc, x, kk),
+            Drw(x, y, n) => xy(0xd, x, y, n as u16 & 0xf),
+            Skp(x) => xkk(0xe, x, 0x9e),
+            Sknp(x) => xkk(0xe, x, 0xa1),
+            LdRegDt(x) => xkk(0xf, x, 0x07),
+            LdRegKey(x) => xkk(0xf, x, 0x0a),
+            LdDtReg(x) => xkk(0xf, x, 0x15),
+            LdStReg(x) => xkk(0xf, x, 0x18),
+            AddIReg(x) => xkk(0xf, x, 0x1e),
+            LdF(x) => xkk(0xf, x, 0x29),
+            LdB(x) => xkk(0xf, x, 0x33),
+            LdMemReg(x) => xkk(0xf, x, 0x55),
+            LdRegMem(x) => xkk(0xf, x, 0x65),
+            Scd(n) => 0x00c0 | (n as u16 & 0xf),
+            Scr => 0x00fb,
+            Scl => 0x00fc,
+            Exit => 0x00fd,
+            Low => 0x00fe,
+            High => 0x00ff,
+            LdHf(x) => xkk(0xf, x, 0x30),
+            LdRReg(x) => xkk(0xf, x, 0x75),
+            LdRegR(x) => xkk(0xf, x, 0x85),
+            Scu(n) => 0x00d0 | (n as u16 & 0xf),
+            LdRangeMem(x, y) => xy(0x5, x, y, 0x2),
+            LdMemRange(x, y) => xy(0x5, x, y, 0x3),
+            LdILong(addr) => return vec![0xf0, 0x00, (addr >> 8) as u8, addr as u8],
+            Plane(n) => xkk(0xf, n, 0x01),
+            Audio => 0xf002,
+            Pitch(x) => xkk(0xf, x, 0x3a),
+            Unknown(word) => word,
+        };
+        vec![(word >> 8) as u8, word as u8]
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            Sys(addr) => write!(f, "SYS 0x{:03X}", addr),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Jp(addr) => write!(f, "JP 0x{:03X}", addr),
+            Call(addr) => write!(f, "CALL 0x{:03X}", addr),
+            SeByte(x, kk) => write!(f, "SE V{:X}, 0x{:02X}", x, kk),
+            SneByte(x, kk) => write!(f, "SNE V{:X}, 0x{:02X}", x, kk),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LdByte(x, kk) => write!(f, "LD V{:X}, 0x{:02X}", x, kk),
+            AddByte(x, kk) => write!(f, "ADD V{:X}, 0x{:02X}", x, kk),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(addr) => write!(f, "LD I, 0x{:03X}", addr),
+            JpOffset(addr) => write!(f, "JP V0, 0x{:03X}", addr),
+            Rnd(x, kk) => write!(f, "RND V{:X}, 0x{:02X}", x, kk),
+            Drw(x, y, n) => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdRegDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdRegKey(x) => write!(f, "LD V{:X}, K", x),
+            LdDtReg(x) => write!(f, "LD DT, V{:X}", x),
+            LdStReg(x) => write!(f, "LD ST, V{:X}", x),
+            AddIReg(x) => write!(f, "ADD I, V{:X}", x),
+            LdF(x) => write!(f, "LD F, V{:X}", x),
+            LdB(x) => write!(f, "LD B, V{:X}", x),
+            LdMemReg(x) => write!(f, "LD [I], V{:X}", x),
+            LdRegMem(x) => write!(f, "LD V{:X}, [I]", x),
+            Scd(n) => write!(f, "SCD {}", n),
+            Scr => write!(f, "SCR"),
+            Scl => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            LdHf(x) => write!(f, "LD HF, V{:X}", x),
+            LdRReg(x) => write!(f, "LD R, V{:X}", x),
+            LdRegR(x) => write!(f, "LD V{:X}, R", x),
+            Scu(n) => write!(f, "SCU {}", n),
+            LdRangeMem(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LdMemRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            LdILong(addr) => write!(f, "LD I, LONG 0x{:04X}", addr),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Unknown(word) => write!(f, "DW 0x{:04X}", word),
+        }
+    }
+}
+
+/// Produce a linear listing of a rom loaded at `origin`, one instruction
+/// per line with its address and raw bytes.
+pub fn listing(rom: &[u8], origin: usize) -> String {
+    let mut out = String::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let (text, len) = match decode_at(rom, offset) {
+            Some((inst, len)) => (inst.to_string(), len),
+            // A trailing odd byte
+            None => (format!("DB 0x{:02X}", rom[offset]), 1),
+        };
+
+        let bytes: Vec<String> = rom[offset..offset + len].iter()
+            .map(|b| format!("{:02X}", b))
+            .collect();
+        out.push_str(&format!("0x{:04X}  {:<11}  {}\n", origin + offset, bytes.join(" "), text));
+        offset += len;
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Sprite,
+    Label,
+    Sub,
+}
+
+impl LabelKind {
+    fn name(&self, addr: usize) -> String {
+        let prefix = match *self {
+            LabelKind::Data => "data",
+            LabelKind::Sprite => "sprite",
+            LabelKind::Label => "label",
+            LabelKind::Sub => "sub",
+        };
+        format!("{}_0x{:03X}", prefix, addr)
+    }
+}
+
+// Results of following control flow through a rom
+struct Flow {
+    // Decoded instructions keyed by rom offset
+    code: BTreeMap<usize, (Instruction, usize)>,
+    is_code: Vec<bool>,
+    is_sprite: Vec<bool>,
+    labels: BTreeMap<usize, LabelKind>,
+}
+
+impl Flow {
+    fn add_label(&mut self, addr: usize, kind: LabelKind) {
+        let entry = self.labels.entry(addr).or_insert(kind);
+        if kind > *entry {
+            *entry = kind;
+        }
+    }
+
+    // Walk every path reachable from the entry point, tracking the value
+    // of I along each one so sprite data drawn with DXYN can be found
+    fn trace(rom: &[u8], origin: usize) -> Flow {
+        use self::Instruction::*;
+
+        let mut flow = Flow {
+            code: BTreeMap::new(),
+            is_code: vec![false; rom.len()],
+            is_sprite: vec![false; rom.len()],
+            labels: BTreeMap::new(),
+        };
+        // Breadth first, so that the I value set up before a call is the
+        // one seen by the first trace through the subroutine
+        let mut pending: VecDeque<(usize, Option<usize>)> = VecDeque::new();
+        pending.push_back((origin, None));
+
+        while let Some((addr, mut i)) = pending.pop_front() {
+            let mut addr = addr;
+
+            loop {
+                if addr < origin || flow.code.contains_key(&(addr - origin)) {
+                    break;
+                }
+                let offset = addr - origin;
+                let (inst, len) = match decode_at(rom, offset) {
+                    Some(decoded) => decoded,
+                    None => break,
+                };
+                // Don't decode over the middle of an instruction that has
+                // already been found
+                if flow.is_code[offset..offset + len].iter().any(|c| *c) {
+                    break;
+                }
+
+                flow.code.insert(offset, (inst, len));
+                for byte in &mut flow.is_code[offset..offset + len] {
+                    *byte = true;
+                }
+                let next = addr + len;
+
+                match inst {
+                    Jp(target) => {
+                        flow.add_label(target as usize, LabelKind::Label);
+                        pending.push_back((target as usize, i));
+                        break;
+                    },
+                    Call(target) => {
+                        flow.add_label(target as usize, LabelKind::Sub);
+                        pending.push_back((target as usize, i));
+                        // The subroutine may have changed I
+                        i = None;
+                    },
+                    // Usually a jump table, the base at least is code
+                    JpOffset(target) => {
+                        flow.add_label(target as usize, LabelKind::Label);
+                        pending.push_back((target as usize, i));
+                        break;
+                    },
+                    Ret | Exit | Unknown(_) => break,
+                    SeByte(..) | SneByte(..) | SeReg(..) | SneReg(..) | Skp(_) | Sknp(_) => {
+                        let skip_len = match decode_at(rom, next - origin) {
+                            Some((_, skip_len)) => skip_len,
+                            None => 2,
+                        };
+                        pending.push_back((next + skip_len, i));
+                    },
+                    LdI(target) | LdILong(target) => {
+                        flow.add_label(target as usize, LabelKind::Data);
+                        i = Some(target as usize);
+                    },
+                    AddIReg(_) | LdF(_) | LdHf(_) => i = None,
+                    Drw(_, _, n) => {
+                        if let Some(sprite) = i {
+                            let sprite_len = if n == 0 { 32 } else { n as usize };
+                            flow.add_label(sprite, LabelKind::Sprite);
+                            for idx in sprite..sprite + sprite_len {
+                                if idx >= origin && idx - origin < rom.len() {
+                                    flow.is_sprite[idx - origin] = true;
+                                }
+                            }
+                        }
+                    },
+                    _ => {},
+                }
+                addr = next;
+            }
+        }
+
+        // Only keep labels that land on an instruction or on data, an
+        // address in the middle of an instruction can't be labelled
+        let labels = flow.labels.clone();
+        for addr in labels.keys() {
+            let valid = *addr >= origin && *addr - origin < rom.len() && {
+                let offset = *addr - origin;
+                flow.code.contains_key(&offset) || !flow.is_code[offset]
+            };
+            if !valid {
+                flow.labels.remove(addr);
+            }
+        }
+        flow
+    }
+
+    fn label(&self, addr: u16) -> Option<String> {
+        self.labels.get(&(addr as usize)).map(|kind| kind.name(addr as usize))
+    }
+
+    // Format an instruction, substituting labels for known addresses
+    fn format(&self, inst: Instruction) -> String {
+        use self::Instruction::*;
+
+        let labelled = match inst {
+            Jp(addr) => self.label(addr).map(|l| format!("JP {}", l)),
+            Call(addr) => self.label(addr).map(|l| format!("CALL {}", l)),
+            LdI(addr) => self.label(addr).map(|l| format!("LD I, {}", l)),
+            JpOffset(addr) => self.label(addr).map(|l| format!("JP V0, {}", l)),
+            LdILong(addr) => self.label(addr).map(|l| format!("LD I, LONG {}", l)),
+            _ => None,
+        };
+        labelled.unwrap_or_else(|| inst.to_string())
+    }
+}
+
+/// Disassemble a rom loaded at `origin` by following control flow from the
+/// entry point, so that only reachable instructions are decoded and
+/// everything else, such as sprites, is emitted as data. The output uses
+/// labels for jump, call and I targets and can be reassembled.
+pub fn flow_listing(rom: &[u8], origin: usize) -> String {
+    let flow = Flow::trace(rom, origin);
+    let mut out = String::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let addr = origin + offset;
+        if let Some(kind) = flow.labels.get(&addr) {
+            out.push_str(&format!("{}:\n", kind.name(addr)));
+        }
+
+        if let Some(&(inst, len)) = flow.code.get(&offset) {
+            out.push_str(&format!("    {}\n", flow.format(inst)));
+            offset += len;
+            continue;
+        }
+
+        // Gather a run of data up to the next label, instruction or
+        // change between sprite and other data
+        let mut end = offset + 1;
+        while end < rom.len() && end - offset < 8 && !flow.is_code[end]
+            && flow.is_sprite[end] == flow.is_sprite[offset]
+            && !flow.labels.contains_key(&(origin + end))
+        {
+            end += 1;
+        }
+
+        let bytes: Vec<String> = rom[offset..end].iter()
+            .map(|b| format!("0x{:02X}", b))
+            .collect();
+        let comment = if flow.is_sprite[offset] { "  ; sprite" } else { "" };
+        out.push_str(&format!("    DB {}{}\n", bytes.join(", "), comment));
+        offset = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::Instruction::*;
+
+    #[test]
+    fn decode_base_set() {
+        assert_eq!(decode(0x00e0), Cls);
+        assert_eq!(decode(0x00ee), Ret);
+        assert_eq!(decode(0x0123), Sys(0x123));
+        assert_eq!(decode(0x01e0), Sys(0x1e0));
+        assert_eq!(decode(0x1abc), Jp(0xabc));
+        assert_eq!(decode(0x3a12), SeByte(0xa, 0x12));
+        assert_eq!(decode(0x5ab0), SeReg(0xa, 0xb));
+        assert_eq!(decode(0x8abe), Shl(0xa, 0xb));
+        assert_eq!(decode(0xdab5), Drw(0xa, 0xb, 5));
+        assert_eq!(decode(0xe29e), Skp(2));
+        assert_eq!(decode(0xf433), LdB(4));
+    }
+
+    #[test]
+    fn decode_extensions() {
+        assert_eq!(decode(0x00c4), Scd(4));
+        assert_eq!(decode(0x00d4), Scu(4));
+        assert_eq!(decode(0x00ff), High);
+        assert_eq!(decode(0xf330), LdHf(3));
+        assert_eq!(decode(0x5ab2), LdRangeMem(0xa, 0xb));
+        assert_eq!(decode(0xf201), Plane(2));
+        assert_eq!(decode(0xf002), Audio);
+        assert_eq!(decode(0xf000), LdILong(0));
+    }
+
+    #[test]
+    fn decode_unknown() {
+        for word in &[0x5ab1, 0x8ab8, 0x9ab1, 0xe2a2, 0xf2ff, 0xf100] {
+            assert_eq!(decode(*word), Unknown(*word));
+        }
+    }
+
+    #[test]
+    fn encode_inverts_decode() {
+        for word in 0..=0xffffu16 {
+            if let LdILong(_) = decode(word) {
+                continue;
+            }
+            let bytes = decode(word).encode();
+            assert_eq!(bytes, [(word >> 8) as u8, word as u8], "{:04x}", word);
+        }
+    }
+
+    #[test]
+    fn decode_at_reads_long_load() {
+        let mem = [0xf0, 0x00, 0x12, 0x34, 0x00, 0xe0, 0x12];
+        assert_eq!(decode_at(&mem, 0), Some((LdILong(0x1234), 4)));
+        assert_eq!(decode_at(&mem, 4), Some((Cls, 2)));
+        // Half an instruction left over
+        assert_eq!(decode_at(&mem, 6), None);
+        // F000 with nothing after it
+        assert_eq!(decode_at(&mem[..2], 0), Some((Unknown(0xf000), 2)));
+    }
+
+    #[test]
+    fn flow_labels() {
+        let rom = [
+            0x22, 0x08, // 200: CALL 208
+            0xa2, 0x0c, // 202: LD I, 20C
+            0xd0, 0x01, // 204: DRW V0, V0, 1
+            0x12, 0x06, // 206: JP 206
+            0xa2, 0x0d, // 208: LD I, 20D
+            0x00, 0xee, // 20A: RET
+            0xff,       // 20C: sprite
+            0x55,       // 20D: data
+        ];
+        let flow = Flow::trace(&rom, 0x200);
+        let labels: Vec<(usize, LabelKind)> = flow.labels.iter().map(|(a, k)| (*a, *k)).collect();
+        assert_eq!(labels, [(0x206, LabelKind::Label), (0x208, LabelKind::Sub),
+                            (0x20c, LabelKind::Sprite), (0x20d, LabelKind::Data)]);
+
+        let listing = flow_listing(&rom, 0x200);
+        assert!(listing.contains("    CALL sub_0x208\n"));
+        assert!(listing.contains("label_0x206:\n    JP label_0x206\n"));
+        assert!(listing.contains("sprite_0x20C:\n    DB 0xFF  ; sprite\n"));
+        assert!(listing.contains("data_0x20D:\n    DB 0x55\n"));
+    }
+
+    #[test]
+    fn listing_addresses_and_bytes() {
+        let text = listing(&[0x60, 0x2a, 0xf0, 0x00, 0x03, 0x00, 0x7f], 0x200);
+        assert_eq!(text, "0x0200  60 2A        LD V0, 0x2A\n\
+                          0x0202  F0 00 03 00  LD I, LONG 0x0300\n\
+                          0x0206  7F           DB 0x7F\n");
+    }
+}
@@ -0,0 +1,362 @@
+use chip8::Core;
+
+/// A condition over the machine state used by debugger breakpoints, such
+/// as `V0 == 5 && I > 0x300`. Operands are numbers, the registers V0-VF,
+/// I, PC, SP, DT and ST, and memory bytes written `[addr]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Num(i64),
+    Reg(Reg),
+    Mem(Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Or,
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    BitOr,
+    BitAnd,
+    Add,
+    Sub,
+}
+
+// Binary operators with their precedence, loosest first
+fn binary_op(token: &str) -> Option<(Op, u8)> {
+    Some(match token {
+        "||" => (Op::Or, 0),
+        "&&" => (Op::And, 1),
+        "==" => (Op::Eq, 2),
+        "!=" => (Op::Ne, 2),
+        "<" => (Op::Lt, 2),
+        "<=" => (Op::Le, 2),
+        ">" => (Op::Gt, 2),
+        ">=" => (Op::Ge, 2),
+        "|" => (Op::BitOr, 3),
+        "&" => (Op::BitAnd, 4),
+        "+" => (Op::Add, 5),
+        "-" => (Op::Sub, 5),
+        _ => return None,
+    })
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_whitespace() {
+            pos += 1;
+        } else if c.is_alphanumeric() {
+            let start = pos;
+            while pos < chars.len() && chars[pos].is_alphanumeric() {
+                pos += 1;
+            }
+            tokens.push(chars[start..pos].iter().collect());
+        } else {
+            let pair: String = chars[pos..(pos + 2).min(chars.len())].iter().collect();
+            if ["==", "!=", "<=", ">=", "&&", "||"].contains(&pair.as_str()) {
+                tokens.push(pair);
+                pos += 2;
+            } else if "<>!|&+-()[]".contains(c) {
+                tokens.push(c.to_string());
+                pos += 1;
+            } else {
+                return Err(format!("unexpected '{}' in expression", c));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_reg(text: &str) -> Option<Reg> {
+    let upper = text.to_uppercase();
+    match upper.as_str() {
+        "I" => Some(Reg::I),
+        "PC" => Some(Reg::Pc),
+        "SP" => Some(Reg::Sp),
+        "DT" => Some(Reg::Dt),
+        "ST" => Some(Reg::St),
+        _ if upper.len() == 2 && upper.starts_with('V') => {
+            u8::from_str_radix(&upper[1..], 16).ok().map(Reg::V)
+        },
+        _ => None,
+    }
+}
+
+fn parse_num(text: &str) -> Option<i64> {
+    let lower = text.to_lowercase();
+    match lower.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => lower.parse().ok(),
+    }
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            },
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token != text {
+            return Err(format!("expected '{}', got '{}'", text, token));
+        }
+        Ok(())
+    }
+
+    // Precedence climbing over the binary operators
+    fn expr(&mut self, min_prec: u8) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some((op, prec)) = self.peek().and_then(binary_op) {
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expr(prec + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        let token = self.next()?;
+        match token.as_str() {
+            "!" => Ok(Expr::Not(Box::new(self.unary()?))),
+            "-" => Ok(Expr::Neg(Box::new(self.unary()?))),
+            "(" => {
+                let expr = self.expr(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            },
+            "[" => {
+                let expr = self.expr(0)?;
+                self.expect("]")?;
+                Ok(Expr::Mem(Box::new(expr)))
+            },
+            _ => match (parse_reg(&token), parse_num(&token)) {
+                (Some(reg), _) => Ok(Expr::Reg(reg)),
+                (None, Some(num)) => Ok(Expr::Num(num)),
+                _ => Err(format!("unexpected '{}' in expression", token)),
+            },
+        }
+    }
+}
+
+/// Parse an expression such as `V0 == 5 && I > 0x300`
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser { tokens: tokenize(text)?, pos: 0 };
+    let expr = parser.expr(0)?;
+    match parser.peek() {
+        Some(token) => Err(format!("unexpected '{}' in expression", token)),
+        None => Ok(expr),
+    }
+}
+
+impl Expr {
+    pub fn eval(&self, core: &Core) -> i64 {
+        let bool = |cond: bool| cond as i64;
+
+        match *self {
+            Expr::Num(num) => num,
+            Expr::Reg(reg) => {
+                let regs = core.registers();
+                match reg {
+                    Reg::V(x) => regs.v[x as usize] as i64,
+                    Reg::I => regs.i as i64,
+                    Reg::Pc => regs.pc as i64,
+                    Reg::Sp => regs.sp as i64,
+                    Reg::Dt => regs.dt as i64,
+                    Reg::St => regs.st as i64,
+                }
+            },
+            Expr::Mem(ref addr) => {
+                let addr = addr.eval(core);
+                if addr < 0 {
+                    0
+                } else {
+                    core.memory().get(addr as usize).map_or(0, |b| *b as i64)
+                }
+            },
+            Expr::Not(ref expr) => bool(expr.eval(core) == 0),
+            Expr::Neg(ref expr) => expr.eval(core).wrapping_neg(),
+            Expr::Binary(op, ref lhs, ref rhs) => {
+                let a = lhs.eval(core);
+                // && and || short circuit as in C
+                match op {
+                    Op::Or if a != 0 => return 1,
+                    Op::And if a == 0 => return 0,
+                    _ => {},
+                }
+                let b = rhs.eval(core);
+                match op {
+                    Op::Or | Op::And => bool(b != 0),
+                    Op::Eq => bool(a == b),
+                    Op::Ne => bool(a != b),
+                    Op::Lt => bool(a < b),
+                    Op::Le => bool(a <= b),
+                    Op::Gt => bool(a > b),
+                    Op::Ge => bool(a >= b),
+                    Op::BitOr => a | b,
+                    Op::BitAnd => a & b,
+                    Op::Add => a.wrapping_add(b),
+                    Op::Sub => a.wrapping_sub(b),
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::Machine;
+    use quirks::Quirks;
+
+    fn core() -> Core {
+        let mut core = Core::new(Machine::Chip8, Quirks::cosmac_vip());
+        core.load_rom(&[0x12, 0x34, 0xab]).unwrap();
+        let mut regs = core.registers();
+        regs.v[0] = 5;
+        regs.v[0xf] = 1;
+        regs.i = 0x202;
+        regs.sp = 2;
+        regs.dt = 3;
+        regs.st = 4;
+        core.set_registers(&regs);
+        core
+    }
+
+    fn eval(text: &str) -> i64 {
+        parse(text).unwrap_or_else(|e| panic!("'{}' doesn't parse: {}", text, e)).eval(&core())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 == 3"), 1);
+        assert_eq!(eval("1 | 2 & 3"), 3);
+        assert_eq!(eval("(1 | 2) & 1"), 1);
+        assert_eq!(eval("0 && 1 || 1"), 1);
+        assert_eq!(eval("1 || 0 && 0"), 1);
+        // Comparisons share a level and group to the left
+        assert_eq!(eval("2 < 3 == 1"), 1);
+        assert_eq!(eval("3 == 3 < 1"), 0);
+        assert_eq!(eval("6 & 3 + 1"), 4);
+    }
+
+    #[test]
+    fn left_associative() {
+        assert_eq!(eval("10 - 3 - 2"), 5);
+        assert_eq!(eval("10 - (3 - 2)"), 9);
+    }
+
+    #[test]
+    fn unary() {
+        assert_eq!(eval("-5 + 2"), -3);
+        assert_eq!(eval("--5"), 5);
+        assert_eq!(eval("!0"), 1);
+        assert_eq!(eval("!7"), 0);
+        assert_eq!(eval("!(V0 == 5)"), 0);
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(eval("V0 < 5"), 0);
+        assert_eq!(eval("V0 <= 5"), 1);
+        assert_eq!(eval("V0 > 4"), 1);
+        assert_eq!(eval("V0 >= 6"), 0);
+        assert_eq!(eval("V0 != 5"), 0);
+        assert_eq!(eval("2 && 3"), 1);
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(eval("0x1F"), 0x1f);
+        assert_eq!(eval("0X1f"), 0x1f);
+        assert_eq!(eval("42"), 42);
+        assert_eq!(eval("9223372036854775807 + 1"), i64::MIN);
+    }
+
+    #[test]
+    fn registers() {
+        assert_eq!(eval("V0"), 5);
+        assert_eq!(eval("vf"), 1);
+        assert_eq!(eval("VA"), 0);
+        assert_eq!(eval("I"), 0x202);
+        assert_eq!(eval("pc"), 0x200);
+        assert_eq!(eval("SP + DT + ST"), 9);
+    }
+
+    #[test]
+    fn memory() {
+        assert_eq!(eval("[0x200]"), 0x12);
+        assert_eq!(eval("[I]"), 0xab);
+        assert_eq!(eval("[PC + 1] == 0x34"), 1);
+        assert_eq!(eval("[[0x201] + 0x1cc]"), 0x12);
+        // Outside memory reads as 0
+        assert_eq!(eval("[-1]"), 0);
+        assert_eq!(eval("[0x10000]"), 0);
+    }
+
+    #[test]
+    fn tree() {
+        let num = |n| Box::new(Expr::Num(n));
+        assert_eq!(parse("V0 == 1 || -[I] & 2").unwrap(), Expr::Binary(
+            Op::Or,
+            Box::new(Expr::Binary(Op::Eq, Box::new(Expr::Reg(Reg::V(0))), num(1))),
+            Box::new(Expr::Binary(Op::BitAnd,
+                                  Box::new(Expr::Neg(Box::new(Expr::Mem(Box::new(Expr::Reg(Reg::I)))))),
+                                  num(2)))));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(parse(""), Err("unexpected end of expression".to_string()));
+        assert_eq!(parse("V0 =="), Err("unexpected end of expression".to_string()));
+        assert_eq!(parse("(1 + 2"), Err("unexpected end of expression".to_string()));
+        assert_eq!(parse("[1 + 2)"), Err("expected ']', got ')'".to_string()));
+        assert_eq!(parse("1 2"), Err("unexpected '2' in expression".to_string()));
+        assert_eq!(parse("V0 $ 1"), Err("unexpected '$' in expression".to_string()));
+        assert_eq!(parse("V0 = 1"), Err("unexpected '=' in expression".to_string()));
+        assert_eq!(parse("VG"), Err("unexpected 'VG' in expression".to_string()));
+        assert_eq!(parse("0x"), Err("unexpected '0x' in expression".to_string()));
+        assert_eq!(parse("99999999999999999999"),
+                   Err("unexpected '99999999999999999999' in expression".to_string()));
+        assert_eq!(parse("1 + )"), Err("unexpected ')' in expression".to_string()));
+    }
+}
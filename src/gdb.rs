@@ -0,0 +1,696 @@
+use chip8::{Core, CoreError, Registers};
+use debugger::Monitor;
+use std::collections::BTreeSet;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+// Registers in the order GDB numbers them: V0-VF, I, PC, SP, DT, ST. Values
+// are sent little endian.
+const REG_SIZES: [usize; 21] = [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 1, 1, 1];
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8r.cpu">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// Signals reported in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Watchpoint {
+    // Z packet type: 2 write, 3 read, 4 access
+    kind: u8,
+    start: usize,
+    end: usize,
+}
+
+impl Watchpoint {
+    fn name(&self) -> &'static str {
+        match self.kind {
+            2 => "watch",
+            3 => "rwatch",
+            _ => "awatch",
+        }
+    }
+}
+
+// What to do after handling a packet
+enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    Detach,
+    Kill,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+    if text.len() % 2 != 0 {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|idx| u8::from_str_radix(text.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_hex(text: &str) -> Option<usize> {
+    usize::from_str_radix(text, 16).ok()
+}
+
+// Parse "addr,len" as sent with m, M and Z packets
+fn parse_range(text: &str) -> Option<(usize, usize)> {
+    let mut parts = text.splitn(2, ',');
+    let addr = parse_hex(parts.next()?)?;
+    let len = parse_hex(parts.next()?)?;
+    Some((addr, len))
+}
+
+fn read_register(regs: &Registers, reg: usize) -> Vec<u8> {
+    match reg {
+        0..=15 => vec![regs.v[reg]],
+        16 => vec![regs.i as u8, (regs.i >> 8) as u8],
+        17 => vec![regs.pc as u8, (regs.pc >> 8) as u8],
+        18 => vec![regs.sp],
+        19 => vec![regs.dt],
+        _ => vec![regs.st],
+    }
+}
+
+fn write_register(regs: &mut Registers, reg: usize, bytes: &[u8]) {
+    let word = bytes[0] as u16 | (*bytes.get(1).unwrap_or(&0) as u16) << 8;
+    match reg {
+        0..=15 => regs.v[reg] = bytes[0],
+        16 => regs.i = word,
+        17 => regs.pc = word,
+        18 => regs.sp = bytes[0],
+        19 => regs.dt = bytes[0],
+        _ => regs.st = bytes[0],
+    }
+}
+
+/// GDB remote serial protocol stub. GDB connects over TCP and drives the
+/// core with the usual register, memory, step, continue, breakpoint and
+/// watchpoint requests.
+pub struct GdbStub {
+    stream: TcpStream,
+    connected: bool,
+    no_ack: bool,
+    paused: bool,
+    // Stop reply owed to GDB once execution stops
+    stop_reply: Option<String>,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    cycles_per_frame: u32,
+    cycle: u32,
+    resuming: bool,
+}
+
+impl GdbStub {
+    /// Wait for GDB to connect on the given localhost port. The core stays
+    /// paused until GDB resumes it.
+    pub fn listen(port: u16, cycles_per_frame: u32) -> io::Result<GdbStub> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        info!("Waiting for GDB to connect on localhost:{}", port);
+        let (stream, addr) = listener.accept()?;
+        info!("GDB connected from {}", addr);
+        GdbStub::new(stream, cycles_per_frame)
+    }
+
+    fn new(stream: TcpStream, cycles_per_frame: u32) -> io::Result<GdbStub> {
+        stream.set_nodelay(true)?;
+
+        Ok(GdbStub {
+            stream,
+            connected: true,
+            no_ack: false,
+            paused: true,
+            stop_reply: None,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            cycles_per_frame,
+            cycle: 0,
+            resuming: false,
+        })
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0u8];
+        match self.stream.read(&mut byte)? {
+            0 => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "GDB disconnected")),
+            _ => Ok(byte[0]),
+        }
+    }
+
+    // Read the next $packet#checksum, acknowledging it
+    fn read_packet(&mut self) -> io::Result<String> {
+        loop {
+            // Skip acks and interrupts that arrive while already stopped
+            while self.read_byte()? != b'$' {}
+
+            let mut data = Vec::new();
+            let mut sum = 0u8;
+            loop {
+                let byte = self.read_byte()?;
+                if byte == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                data.push(byte);
+            }
+
+            let check = [self.read_byte()?, self.read_byte()?];
+            let valid = ::std::str::from_utf8(&check).ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()) == Some(sum);
+
+            if self.no_ack {
+                return Ok(String::from_utf8_lossy(&data).into_owned());
+            }
+            if valid {
+                self.stream.write_all(b"+")?;
+                return Ok(String::from_utf8_lossy(&data).into_owned());
+            }
+            self.stream.write_all(b"-")?;
+        }
+    }
+
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        let packet = format!("${}#{:02x}", data, sum);
+        loop {
+            self.stream.write_all(packet.as_bytes())?;
+            if self.no_ack || self.read_byte()? == b'+' {
+                return Ok(());
+            }
+        }
+    }
+
+    // Check for a ^C from GDB without blocking
+    fn interrupted(&mut self) -> bool {
+        let mut buf = [0u8; 64];
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let result = self.stream.read(&mut buf);
+        let _ = self.stream.set_nonblocking(false);
+
+        match result {
+            Ok(0) => {
+                self.disconnect();
+                false
+            },
+            Ok(len) => buf[..len].contains(&0x03),
+            Err(_) => false,
+        }
+    }
+
+    // Let the program run on freely once GDB goes away
+    fn disconnect(&mut self) {
+        info!("GDB disconnected");
+        self.connected = false;
+        self.paused = false;
+        self.breakpoints.clear();
+        self.watchpoints.clear();
+    }
+
+    fn step(&mut self, core: &mut Core) -> Result<(), CoreError> {
+        core.tick()?;
+        self.cycle += 1;
+        if self.cycle >= self.cycles_per_frame {
+            self.cycle = 0;
+            core.tick_timers();
+        }
+        Ok(())
+    }
+
+    // Execute an instruction, returning the stop reply for any watchpoint
+    // it hit
+    fn step_watched(&mut self, core: &mut Core) -> Result<Option<String>, CoreError> {
+        self.step(core)?;
+
+        for access in core.accesses() {
+            for watch in &self.watchpoints {
+                let hit = match watch.kind {
+                    2 => access.write,
+                    3 => !access.write,
+                    _ => true,
+                };
+                if hit && access.addr >= watch.start && access.addr < watch.end {
+                    return Ok(Some(format!("T{:02x}{}:{:x};", SIGTRAP, watch.name(), access.addr)));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn fault_reply(e: &CoreError) -> String {
+        match *e {
+            CoreError::UnknownOpcode { .. } => format!("S{:02x}", SIGILL),
+            _ => format!("S{:02x}", SIGSEGV),
+        }
+    }
+
+    fn handle(&mut self, core: &mut Core, packet: &str) -> Action {
+        let reply = |text: &str| Action::Reply(text.to_string());
+        let error = reply("E01");
+
+        let (cmd, args) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        match cmd {
+            "?" => Action::Reply(self.stop_reply.clone().unwrap_or(format!("S{:02x}", SIGTRAP))),
+            "g" => {
+                let regs = core.registers();
+                let bytes: Vec<u8> = (0..REG_SIZES.len())
+                    .flat_map(|reg| read_register(&regs, reg))
+                    .collect();
+                Action::Reply(to_hex(&bytes))
+            },
+            "G" => match from_hex(args) {
+                Some(ref bytes) if bytes.len() == REG_SIZES.iter().sum::<usize>() => {
+                    let mut regs = core.registers();
+                    let mut offset = 0;
+                    for (reg, size) in REG_SIZES.iter().enumerate() {
+                        write_register(&mut regs, reg, &bytes[offset..offset + size]);
+                        offset += size;
+                    }
+                    core.set_registers(&regs);
+                    reply("OK")
+                },
+                _ => error,
+            },
+            "p" => match parse_hex(args) {
+                Some(reg) if reg < REG_SIZES.len() => {
+                    Action::Reply(to_hex(&read_register(&core.registers(), reg)))
+                },
+                _ => error,
+            },
+            "P" => {
+                let mut parts = args.splitn(2, '=');
+                match (parts.next().and_then(parse_hex), parts.next().and_then(from_hex)) {
+                    (Some(reg), Some(bytes)) if reg < REG_SIZES.len() && bytes.len() == REG_SIZES[reg] => {
+                        let mut regs = core.registers();
+                        write_register(&mut regs, reg, &bytes);
+                        core.set_registers(&regs);
+                        reply("OK")
+                    },
+                    _ => error,
+                }
+            },
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    match addr.checked_add(len).and_then(|end| core.memory().get(addr..end)) {
+                        Some(bytes) => Action::Reply(to_hex(bytes)),
+                        None => error,
+                    }
+                },
+                None => error,
+            },
+            "M" => {
+                let mut parts = args.splitn(2, ':');
+                match (parts.next().and_then(parse_range), parts.next().and_then(from_hex)) {
+                    (Some((addr, len)), Some(ref bytes)) if bytes.len() == len => {
+                        if core.write_memory(addr, bytes) { reply("OK") } else { error }
+                    },
+                    _ => error,
+                }
+            },
+            "s" => Action::Step,
+            "c" => Action::Continue,
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            "Z" | "z" => {
+                let insert = cmd == "Z";
+                let mut parts = args.splitn(2, ',');
+                let kind = parts.next().and_then(|k| k.parse::<u8>().ok());
+                let range = parts.next().and_then(parse_range);
+                match (kind, range) {
+                    // Software and hardware breakpoints are the same thing
+                    // here
+                    (Some(0), Some((addr, _))) | (Some(1), Some((addr, _))) if addr <= 0xffff => {
+                        if insert {
+                            self.breakpoints.insert(addr as u16);
+                        } else {
+                            self.breakpoints.remove(&(addr as u16));
+                        }
+                        reply("OK")
+                    },
+                    (Some(kind), Some((start, len))) if (2..=4).contains(&kind) => {
+                        let end = match start.checked_add(len.max(1)) {
+                            Some(end) => end,
+                            None => return error,
+                        };
+                        let watch = Watchpoint { kind, start, end };
+                        if insert {
+                            self.watchpoints.push(watch);
+                        } else {
+                            self.watchpoints.retain(|w| *w != watch);
+                        }
+                        reply("OK")
+                    },
+                    _ => reply(""),
+                }
+            },
+            "H" => reply("OK"),
+            "T" => reply("OK"),
+            _ => self.query(packet),
+        }
+    }
+
+    // General queries and settings
+    fn query(&self, packet: &str) -> Action {
+        let reply = |text: &str| Action::Reply(text.to_string());
+
+        if packet.starts_with("qSupported") {
+            reply("PacketSize=1000;qXfer:features:read+;QStartNoAckMode+")
+        } else if packet == "QStartNoAckMode" {
+            // Acks stop once the reply has gone out
+            reply("OK")
+        } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            match parse_range(range) {
+                Some((offset, len)) => {
+                    let xml = TARGET_XML.as_bytes();
+                    let start = offset.min(xml.len());
+                    let end = start.saturating_add(len).min(xml.len());
+                    let more = if end < xml.len() { "m" } else { "l" };
+                    Action::Reply(format!("{}{}", more, String::from_utf8_lossy(&xml[start..end])))
+                },
+                None => reply("E01"),
+            }
+        } else if packet == "qAttached" {
+            reply("1")
+        } else if packet == "qC" {
+            reply("QC1")
+        } else if packet == "qfThreadInfo" {
+            reply("m1")
+        } else if packet == "qsThreadInfo" {
+            reply("l")
+        } else if packet.starts_with("qSymbol") {
+            reply("OK")
+        } else {
+            // Unsupported
+            reply("")
+        }
+    }
+}
+
+impl Monitor for GdbStub {
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    fn pause(&mut self) {
+        if self.connected && !self.paused {
+            self.paused = true;
+            self.stop_reply = Some(format!("S{:02x}", SIGINT));
+        }
+    }
+
+    fn run_frame(&mut self, core: &mut Core) -> Result<(), CoreError> {
+        if !self.connected {
+            return core.run_frame(self.cycles_per_frame);
+        }
+        if self.interrupted() {
+            self.pause();
+            return Ok(());
+        }
+        core.observe_memory(!self.watchpoints.is_empty());
+
+        while !self.paused {
+            let pc = core.registers().pc;
+            if !self.resuming && self.breakpoints.contains(&pc) {
+                self.paused = true;
+                self.stop_reply = Some(format!("S{:02x}", SIGTRAP));
+                break;
+            }
+            self.resuming = false;
+
+            match self.step_watched(core) {
+                Ok(Some(stop)) => {
+                    self.paused = true;
+                    self.stop_reply = Some(stop);
+                },
+                Ok(None) => {},
+                Err(e) => {
+                    self.paused = true;
+                    self.stop_reply = Some(GdbStub::fault_reply(&e));
+                    return Err(e);
+                },
+            }
+            if self.cycle == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn interact(&mut self, core: &mut Core) -> bool {
+        if let Some(stop) = self.stop_reply.clone() {
+            if self.send(&stop).is_err() {
+                self.disconnect();
+                return true;
+            }
+        }
+
+        while self.paused {
+            let packet = match self.read_packet() {
+                Ok(packet) => packet,
+                Err(_) => {
+                    self.disconnect();
+                    break;
+                },
+            };
+
+            let reply = match self.handle(core, &packet) {
+                Action::Reply(reply) => reply,
+                Action::Step => {
+                    core.observe_memory(!self.watchpoints.is_empty());
+                    match self.step_watched(core) {
+                        Ok(stop) => stop.unwrap_or(format!("S{:02x}", SIGTRAP)),
+                        Err(e) => GdbStub::fault_reply(&e),
+                    }
+                },
+                Action::Continue => {
+                    self.paused = false;
+                    self.resuming = true;
+                    self.stop_reply = None;
+                    break;
+                },
+                Action::Detach => {
+                    let _ = self.send("OK");
+                    self.disconnect();
+                    break;
+                },
+                Action::Kill => return false,
+            };
+
+            if self.send(&reply).is_err() {
+                self.disconnect();
+            }
+            if packet == "QStartNoAckMode" {
+                self.no_ack = true;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::Machine;
+    use quirks::Quirks;
+
+    // A stub wired to a loopback socket playing the part of GDB
+    fn connect() -> (GdbStub, TcpStream) {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let gdb = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        (GdbStub::new(stream, 10).unwrap(), gdb)
+    }
+
+    fn stub() -> GdbStub {
+        connect().0
+    }
+
+    fn core() -> Core {
+        Core::new(Machine::Chip8, Quirks::cosmac_vip())
+    }
+
+    fn reply(stub: &mut GdbStub, core: &mut Core, packet: &str) -> String {
+        match stub.handle(core, packet) {
+            Action::Reply(reply) => reply,
+            _ => panic!("no reply to {}", packet),
+        }
+    }
+
+    fn read_exact(gdb: &mut TcpStream, len: usize) -> String {
+        let mut buf = vec![0; len];
+        gdb.read_exact(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0x12]), "00ab12");
+        assert_eq!(from_hex("00aB12"), Some(vec![0x00, 0xab, 0x12]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+        assert_eq!(parse_range("200,1a"), Some((0x200, 0x1a)));
+        assert_eq!(parse_range("200"), None);
+    }
+
+    #[test]
+    fn packets_are_acked_when_the_checksum_matches() {
+        let (mut stub, mut gdb) = connect();
+        gdb.write_all(b"+$g#67").unwrap();
+        assert_eq!(stub.read_packet().unwrap(), "g");
+        assert_eq!(read_exact(&mut gdb, 1), "+");
+    }
+
+    #[test]
+    fn bad_checksums_are_nacked() {
+        let (mut stub, mut gdb) = connect();
+        gdb.write_all(b"$g#00$m200,2#5d").unwrap();
+        assert_eq!(stub.read_packet().unwrap(), "m200,2");
+        assert_eq!(read_exact(&mut gdb, 2), "-+");
+    }
+
+    #[test]
+    fn no_ack_mode_skips_acks() {
+        let (mut stub, mut gdb) = connect();
+        stub.no_ack = true;
+        gdb.write_all(b"$?#3f").unwrap();
+        assert_eq!(stub.read_packet().unwrap(), "?");
+        stub.send("OK").unwrap();
+        assert_eq!(read_exact(&mut gdb, 6), "$OK#9a");
+    }
+
+    #[test]
+    fn replies_are_resent_until_acked() {
+        let (mut stub, mut gdb) = connect();
+        gdb.write_all(b"-+").unwrap();
+        stub.send("S05").unwrap();
+        assert_eq!(read_exact(&mut gdb, 14), "$S05#b8$S05#b8");
+    }
+
+    #[test]
+    fn read_registers() {
+        let (mut stub, mut core) = (stub(), core());
+        let mut regs = core.registers();
+        regs.v[0] = 0x12;
+        regs.v[15] = 0x01;
+        regs.i = 0x0345;
+        regs.pc = 0x0206;
+        regs.sp = 2;
+        regs.dt = 3;
+        regs.st = 4;
+        core.set_registers(&regs);
+
+        assert_eq!(reply(&mut stub, &mut core, "g"),
+                   concat!("12000000000000000000000000000001", "4503", "0602", "020304"));
+        assert_eq!(reply(&mut stub, &mut core, "p11"), "0602");
+        assert_eq!(reply(&mut stub, &mut core, "p15"), "E01");
+    }
+
+    #[test]
+    fn write_registers() {
+        let (mut stub, mut core) = (stub(), core());
+        // V0-VF, then I and PC little endian, then SP, DT and ST
+        let packet = concat!("G0102030405060708090a0b0c0d0e0f10", "3412", "0004", "010506");
+        assert_eq!(reply(&mut stub, &mut core, packet), "OK");
+
+        let regs = core.registers();
+        assert_eq!(regs.v[0], 0x01);
+        assert_eq!(regs.v[15], 0x10);
+        assert_eq!(regs.i, 0x1234);
+        assert_eq!(regs.pc, 0x0400);
+        assert_eq!((regs.sp, regs.dt, regs.st), (1, 5, 6));
+
+        assert_eq!(reply(&mut stub, &mut core, "G0102"), "E01");
+        assert_eq!(reply(&mut stub, &mut core, "P10=cdab"), "OK");
+        assert_eq!(core.registers().i, 0xabcd);
+        assert_eq!(reply(&mut stub, &mut core, "P0=cdab"), "E01");
+    }
+
+    #[test]
+    fn read_and_write_memory() {
+        let (mut stub, mut core) = (stub(), core());
+        core.load_rom(&[0x60, 0x2a, 0x00, 0xe0]).unwrap();
+
+        assert_eq!(reply(&mut stub, &mut core, "m200,4"), "602a00e0");
+        assert_eq!(reply(&mut stub, &mut core, "M202,2:1234"), "OK");
+        assert_eq!(&core.memory()[0x200..0x204], &[0x60, 0x2a, 0x12, 0x34]);
+
+        assert_eq!(reply(&mut stub, &mut core, "mffe,4"), "E01");
+        assert_eq!(reply(&mut stub, &mut core, "M202,3:1234"), "E01");
+        assert_eq!(reply(&mut stub, &mut core, "Mfff,2:1234"), "E01");
+    }
+
+    #[test]
+    fn ranges_near_the_top_of_the_address_space() {
+        let (mut stub, mut core) = (stub(), core());
+        let top = format!("{:x}", usize::MAX);
+
+        assert_eq!(reply(&mut stub, &mut core, &format!("m{},2", top)), "E01");
+        assert_eq!(reply(&mut stub, &mut core, &format!("M{},2:1234", top)), "E01");
+        assert_eq!(reply(&mut stub, &mut core, &format!("Z2,{},2", top)), "E01");
+        assert!(stub.watchpoints.is_empty());
+    }
+
+    #[test]
+    fn target_description() {
+        let (mut stub, mut core) = (stub(), core());
+        let xml = TARGET_XML;
+        let first = reply(&mut stub, &mut core, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(first, format!("m{}", &xml[..0x10]));
+        let rest = reply(&mut stub, &mut core, "qXfer:features:read:target.xml:10,ffff");
+        assert_eq!(rest, format!("l{}", &xml[0x10..]));
+
+        let huge = format!("qXfer:features:read:target.xml:1,{:x}", usize::MAX);
+        assert_eq!(reply(&mut stub, &mut core, &huge), format!("l{}", &xml[1..]));
+        let past_end = format!("qXfer:features:read:target.xml:{:x},1", usize::MAX);
+        assert_eq!(reply(&mut stub, &mut core, &past_end), "l");
+    }
+
+    #[test]
+    fn breakpoints_and_watchpoints() {
+        let (mut stub, mut core) = (stub(), core());
+        assert_eq!(reply(&mut stub, &mut core, "Z0,204,2"), "OK");
+        assert!(stub.breakpoints.contains(&0x204));
+        assert_eq!(reply(&mut stub, &mut core, "z0,204,2"), "OK");
+        assert!(stub.breakpoints.is_empty());
+
+        assert_eq!(reply(&mut stub, &mut core, "Z2,300,0"), "OK");
+        assert_eq!(stub.watchpoints, [Watchpoint { kind: 2, start: 0x300, end: 0x301 }]);
+        assert_eq!(reply(&mut stub, &mut core, "z2,300,0"), "OK");
+        assert!(stub.watchpoints.is_empty());
+    }
+}
@@ -0,0 +1,210 @@
+use chip8::{Core, CoreError};
+use state::crc32;
+use std::io::{self, Write};
+
+/// How long a headless run lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Cycles(u64),
+    Frames(u64),
+}
+
+/// A scripted key press or release, applied at the start of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: usize,
+    pub pressed: bool,
+}
+
+/// Parse a key script: events separated by commas or whitespace, each one
+/// of `FRAME+KEY` (press), `FRAME-KEY` (release) or `FRAME:KEY` (press,
+/// then release a frame later). Frames count from 0, keys are hex digits.
+/// `#` starts a comment running to the end of the line.
+pub fn parse_keys(script: &str) -> Result<Vec<KeyEvent>, String> {
+    let mut events = Vec::new();
+
+    for line in script.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        for event in line.split(|c: char| c == ',' || c.is_whitespace()) {
+            if event.is_empty() {
+                continue;
+            }
+            let split = event.find(['+', '-', ':'])
+                .ok_or(format!("invalid key event '{}'", event))?;
+            let frame: u64 = event[..split].parse()
+                .map_err(|_| format!("invalid frame in key event '{}'", event))?;
+            let key = match usize::from_str_radix(&event[split + 1..], 16) {
+                Ok(key) if key < 0x10 => key,
+                _ => return Err(format!("invalid key in key event '{}'", event)),
+            };
+            match &event[split..split + 1] {
+                "+" => events.push(KeyEvent { frame, key, pressed: true }),
+                "-" => events.push(KeyEvent { frame, key, pressed: false }),
+                _ => {
+                    let release = frame.checked_add(1)
+                        .ok_or(format!("invalid frame in key event '{}'", event))?;
+                    events.push(KeyEvent { frame, key, pressed: true });
+                    events.push(KeyEvent { frame: release, key, pressed: false });
+                },
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Run the core until `limit` is reached, with no display or sound. Timers
+/// tick after every `cycles_per_frame` instructions as they would in the
+/// window, and key events are applied at the start of their frame.
+pub fn run(core: &mut Core, limit: Limit, keys: &[KeyEvent],
+           cycles_per_frame: u32) -> Result<(), CoreError> {
+    let cycles_per_frame = cycles_per_frame as u64;
+    let total = match limit {
+        Limit::Cycles(cycles) => cycles,
+        // Anything this long never finishes anyway
+        Limit::Frames(frames) => frames.saturating_mul(cycles_per_frame),
+    };
+
+    let mut frame = 0;
+    let mut cycles = 0;
+    while cycles < total {
+        for event in keys.iter().filter(|e| e.frame == frame) {
+            if event.pressed {
+                core.set_key(event.key);
+            } else {
+                core.clear_key(event.key);
+            }
+        }
+
+        let run = cycles_per_frame.min(total - cycles);
+        for _ in 0..run {
+            core.tick()?;
+        }
+        if run == cycles_per_frame {
+            core.tick_timers();
+        }
+        cycles += run;
+        frame += 1;
+    }
+    Ok(())
+}
+
+/// The display as text, a line per row. Pixels are `.` when off and `#`
+/// when on, or the hex value of their planes when more than one is set.
+pub fn screen_text(core: &Core) -> String {
+    let (width, _) = core.resolution();
+    let mut text = String::new();
+    for row in core.frame_buffer().chunks(width) {
+        text.extend(row.iter().map(|pixel| match *pixel {
+            0 => '.',
+            1 => '#',
+            n => format!("{:X}", n).chars().next().unwrap(),
+        }));
+        text.push('\n');
+    }
+    text
+}
+
+/// The registers as text, in the same layout as the debugger
+pub fn register_text(core: &Core) -> String {
+    let regs = core.registers();
+    let mut text = String::new();
+    for (row, chunk) in regs.v.chunks(8).enumerate() {
+        let values: Vec<String> = chunk.iter().enumerate()
+            .map(|(idx, v)| format!("V{:X}={:02X}", row * 8 + idx, v))
+            .collect();
+        text.push_str(&values.join(" "));
+        text.push('\n');
+    }
+    text.push_str(&format!("I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}\n",
+                           regs.i, regs.pc, regs.sp, regs.dt, regs.st));
+    text
+}
+
+fn png_chunk<W: Write>(out: &mut W, kind: &[u8], data: &[u8]) -> io::Result<()> {
+    let mut body = kind.to_vec();
+    body.extend_from_slice(data);
+    out.write_all(&(data.len() as u32).to_be_bytes())?;
+    out.write_all(&body)?;
+    out.write_all(&crc32(&body).to_be_bytes())
+}
+
+// zlib stream made of uncompressed deflate blocks, screens are small
+// enough that compressing them isn't worth the code
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(blocks.peek().is_none() as u8);
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+    out
+}
+
+/// Write the display as a paletted PNG, one image pixel per CHIP-8 pixel.
+/// Each pixel's plane bits pick its color from `palette`.
+pub fn write_png<W: Write>(out: &mut W, core: &Core, palette: &[[u8; 3]; 16]) -> io::Result<()> {
+    let (width, height) = core.resolution();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit indexed color, default compression, filter and interlacing
+    header.extend_from_slice(&[8, 3, 0, 0, 0]);
+
+    let colors: Vec<u8> = palette.iter().flat_map(|rgb| rgb.iter().cloned()).collect();
+
+    // Each scanline starts with its filter type, always none here
+    let mut image = Vec::with_capacity((width + 1) * height);
+    for row in core.frame_buffer().chunks(width) {
+        image.push(0);
+        image.extend(row.iter().map(|pixel| pixel & 0xf));
+    }
+
+    out.write_all(b"\x89PNG\r\n\x1a\n")?;
+    png_chunk(out, b"IHDR", &header)?;
+    png_chunk(out, b"PLTE", &colors)?;
+    png_chunk(out, b"IDAT", &zlib_stored(&image))?;
+    png_chunk(out, b"IEND", &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_scripts() {
+        let events = parse_keys("3+A, 10-a # comment\n5:0").unwrap();
+        assert_eq!(events, [
+            KeyEvent { frame: 3, key: 0xa, pressed: true },
+            KeyEvent { frame: 10, key: 0xa, pressed: false },
+            KeyEvent { frame: 5, key: 0, pressed: true },
+            KeyEvent { frame: 6, key: 0, pressed: false },
+        ]);
+        assert_eq!(parse_keys("  # nothing\n"), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn bad_key_scripts() {
+        assert_eq!(parse_keys("3A"), Err("invalid key event '3A'".to_string()));
+        assert_eq!(parse_keys("x+1"), Err("invalid frame in key event 'x+1'".to_string()));
+        assert_eq!(parse_keys("1+10"), Err("invalid key in key event '1+10'".to_string()));
+        let last = format!("{}:1", u64::MAX);
+        assert_eq!(parse_keys(&last), Err(format!("invalid frame in key event '{}'", last)));
+        let past_last = format!("{}0+1", u64::MAX);
+        assert_eq!(parse_keys(&past_last), Err(format!("invalid frame in key event '{}'", past_last)));
+    }
+}
@@ -0,0 +1,55 @@
+//! CHIP-8, SUPER-CHIP and XO-CHIP interpreter core along with the tools
+//! built around it. The SDL frontend lives in the `chip8r` binary behind
+//! the default `sdl` feature, the library itself doesn't depend on SDL.
+//!
+//! Turning off the default `std` feature leaves just the interpreter core
+//! (`chip8`, `quirks` and `disasm`) building under `no_std`. It still needs
+//! an allocator, and as there's no thread RNG CXNN draws from a fixed seed
+//! `XorShift` unless `Core::set_random` says otherwise.
+//!
+//! Running a ROM for a frame at a time:
+//!
+//! ```
+//! use chip8r::{Core, Machine, Quirks};
+//!
+//! let mut core = Core::new(Machine::Chip8, Quirks::cosmac_vip());
+//! // 00E0: clear the screen, 1200: jump back to it
+//! core.load_rom(&[0x00, 0xe0, 0x12, 0x00]).unwrap();
+//! core.run_frame(14).unwrap();
+//! assert_eq!(core.cycles(), 14);
+//! ```
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "std")]
+extern crate core;
+#[macro_use]
+extern crate alloc;
+#[macro_use]
+extern crate log;
+
+#[cfg(feature = "std")]
+pub mod asm;
+pub mod chip8;
+#[cfg(feature = "std")]
+pub mod debugger;
+pub mod disasm;
+#[cfg(feature = "std")]
+pub mod expr;
+#[cfg(feature = "std")]
+pub mod gdb;
+#[cfg(feature = "std")]
+pub mod headless;
+#[cfg(feature = "std")]
+pub mod octo;
+pub mod quirks;
+#[cfg(feature = "std")]
+pub mod rewind;
+mod state;
+#[cfg(feature = "std")]
+pub mod trace;
+
+pub use chip8::{Core, CoreError, LoadError, Machine, MemorySize, Random, Registers, Tracer,
+                XorShift};
+pub use quirks::{Preset, Quirks};
+pub use state::StateError;
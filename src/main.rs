@@ -1,60 +1,563 @@
-mod chip8;
+extern crate chip8r;
+#[macro_use]
+extern crate log;
+#[cfg(feature = "sdl")]
 extern crate sdl2;
 
+// Without the sdl feature only the command line tools and headless runs are
+// built
+#[cfg(feature = "sdl")]
+mod beeper;
+
+use chip8r::{asm, chip8, disasm, headless, octo, quirks, trace};
+#[cfg(feature = "sdl")]
+use chip8r::{debugger, gdb, rewind};
+#[cfg(feature = "sdl")]
 use sdl2::gfx::primitives::DrawRenderer;
+#[cfg(feature = "sdl")]
 use sdl2::pixels::Color;
+#[cfg(feature = "sdl")]
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+#[cfg(feature = "sdl")]
+use sdl2::keyboard::{Keycode, LSHIFTMOD, RSHIFTMOD};
+#[cfg(feature = "sdl")]
 use sdl2::render::Canvas;
-use sdl2::rect::Point;
-use std::time::Duration;
+#[cfg(feature = "sdl")]
+use std::time::{Duration, Instant};
 use std::{env};
 use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::io::prelude::*;
 use std::process;
+#[cfg(feature = "sdl")]
+use debugger::Monitor;
 
+#[cfg(feature = "sdl")]
 const SCREEN_WIDTH: usize = 1280;
+#[cfg(feature = "sdl")]
 const SCREEN_HEIGHT: usize = 640;
-const SCALING_FACTOR: usize = SCREEN_WIDTH / chip8::SCREEN_X;
 const CORE_FREQ: u64 = 840;
+const CYCLES_PER_FRAME: u32 = (CORE_FREQ / chip8::TIMER_FREQ) as u32;
+
+#[cfg(feature = "sdl")]
+fn draw_frame_buffer(canvas: &mut Canvas<sdl2::video::Window>, palette: &[Color; 16],
+                     pixels: &[u8], (width, height): (usize, usize)) {
+    // Keep the window size fixed and scale to whatever resolution the
+    // core is currently running at
+    let scale = (SCREEN_WIDTH / width) as f32;
+    canvas.set_scale(scale, scale).unwrap();
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = pixels[y * width + x] as usize;
+            if pixel != 0 {
+                canvas.pixel(x as i16, y as i16, palette[pixel & 0xf]).unwrap();
+            }
+        }
+    }
+}
+
+// Colors for each combination of XO-CHIP bitplanes. Without XO-CHIP
+// only the first two are used.
+const DEFAULT_PALETTE: [u32; 16] = [0x000000, 0x00ff00, 0xff00ff, 0xffffff,
+                                    0x202020, 0x40c040, 0xc040c0, 0xc0c0c0,
+                                    0x404040, 0x208020, 0x802080, 0x808080,
+                                    0x606060, 0x106010, 0x601060, 0xa0a0a0];
+
+// F1-F10 select save slots 1-10
+#[cfg(feature = "sdl")]
+fn state_slot(keycode: Keycode) -> Option<u32> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        Keycode::F10 => Some(10),
+        _ => None,
+    }
+}
+
+// Save states live next to the rom, one file per slot
+#[cfg(feature = "sdl")]
+fn state_path(rom_path: &str, slot: u32) -> String {
+    format!("{}.state{}", rom_path, slot)
+}
+
+#[cfg(feature = "sdl")]
+fn save_state(core: &chip8::Core, rom_path: &str, slot: u32) {
+    let path = state_path(rom_path, slot);
+    match File::create(&path).and_then(|mut f| f.write_all(&core.save_state())) {
+        Ok(()) => println!("Saved state to slot {} ({})", slot, path),
+        Err(e) => println!("Failed to save state to {}: {}", path, e),
+    }
+}
+
+#[cfg(feature = "sdl")]
+fn load_state(core: &mut chip8::Core, rom_path: &str, slot: u32) -> bool {
+    let path = state_path(rom_path, slot);
+    let mut data = Vec::new();
+    if let Err(e) = File::open(&path).and_then(|mut f| f.read_to_end(&mut data)) {
+        println!("Failed to read state from {}: {}", path, e);
+        return false;
+    }
+
+    match core.load_state(&data) {
+        Ok(()) => {
+            println!("Loaded state from slot {} ({})", slot, path);
+            true
+        },
+        Err(e) => {
+            println!("Failed to load state from {}: {}", path, e);
+            false
+        }
+    }
+}
+
+struct Options {
+    rom_path: String,
+    #[cfg(feature = "sdl")]
+    beeper: beeper::BeeperConfig,
+    machine: chip8::Machine,
+    quirks: Option<quirks::Preset>,
+    memory: Option<chip8::MemorySize>,
+    load_addr: u16,
+    palette: [[u8; 3]; 16],
+    #[cfg(feature = "sdl")]
+    rewind_seconds: usize,
+    #[cfg(feature = "sdl")]
+    rewind_mib: usize,
+    #[cfg(feature = "sdl")]
+    debug: bool,
+    #[cfg(feature = "sdl")]
+    gdb_port: Option<u16>,
+    trace_path: Option<String>,
+    log_level: log::LevelFilter,
+    headless: bool,
+    limit: Option<headless::Limit>,
+    keys: Vec<headless::KeyEvent>,
+    screen_path: Option<String>,
+}
+
+fn usage() -> ! {
+    println!("Usage: chip8r [options] <rom>");
+    println!("       chip8r run --headless (--cycles <n> | --frames <n>) [options] <rom>");
+    println!("       chip8r disasm [--flow] <rom>");
+    println!("       chip8r asm <source> [-o <rom>]");
+    println!("       chip8r octo <source.8o> [-o <rom>]");
+    println!();
+    println!("Options:");
+    println!("    --machine <type>    Machine to emulate: chip8 (default), schip,");
+    println!("                        xochip");
+    println!("    --quirks <preset>   Instruction quirks: vip, chip48, schip, octo");
+    println!("                        (default matches the machine)");
+    println!("    --memory <size>     Memory size: 4k or 64k (default matches the");
+    println!("                        machine)");
+    println!("    --load-addr <addr>  Address to load and start the ROM at (default");
+    println!("                        0x200, ETI-660 programs use 0x600)");
+    println!("    --palette <colors>  Comma separated RRGGBB colors, background");
+    println!("                        first then one per bitplane combination");
+    if cfg!(feature = "sdl") {
+        println!("    --tone <hz>         Buzzer frequency (default 440)");
+        println!("    --waveform <wave>   Buzzer waveform: square, sine, triangle");
+        println!("    --volume <0-100>    Buzzer volume (default 25)");
+        println!("    --mute              Start with sound muted (toggle with M)");
+        println!("    --rewind <seconds>  Rewind history to keep (default 30, 0 disables)");
+        println!("    --rewind-mem <MiB>  Memory cap for the rewind history (default 64)");
+        println!("    --debug             Start paused in the command line debugger");
+        println!("    --gdb <port>        Wait for GDB to connect on localhost:<port>");
+    }
+    println!("    --trace <file>      Write a line per instruction executed to file");
+    println!("    --log <level>       Log level: off, error, warn (default), info,");
+    println!("                        debug or trace (every instruction)");
+    println!();
+    println!("Headless options:");
+    println!("    --headless          Run without a window or sound, then print the");
+    println!("                        registers and display");
+    println!("    --cycles <n>        Stop after n instructions");
+    println!("    --frames <n>        Stop after n 60 Hz frames");
+    println!("    --keys <script>     Key events, FRAME+KEY presses, FRAME-KEY");
+    println!("                        releases and FRAME:KEY taps, comma separated.");
+    println!("                        Use @<file> to read them from a file");
+    println!("    --screen <file>     Save the display to file instead of printing it,");
+    println!("                        as a PNG if the name ends in .png");
+    if cfg!(feature = "sdl") {
+        println!();
+        println!("Hold Tab to rewind.");
+        println!("Shift+F1-F10 saves the machine state to a slot, F1-F10 loads it.");
+        println!("With --debug or --gdb, F12 breaks into the debugger.");
+    }
+    process::exit(1);
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: Option<&String>) -> T {
+    match value.map(|v| v.parse()) {
+        Some(Ok(v)) => v,
+        _ => {
+            println!("Invalid or missing value for {}", flag);
+            usage();
+        }
+    }
+}
+
+// Addresses are hex with a 0x prefix or decimal without
+fn parse_addr(flag: &str, value: Option<&String>) -> u16 {
+    let parsed = value.map(|v| match v.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => v.parse(),
+    });
+    match parsed {
+        Some(Ok(addr)) => addr,
+        _ => {
+            println!("Invalid or missing value for {}", flag);
+            usage();
+        }
+    }
+}
+
+fn rgb(rgb: u32) -> [u8; 3] {
+    [(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8]
+}
+
+fn default_palette() -> [[u8; 3]; 16] {
+    let mut palette = [[0; 3]; 16];
+    for (color, value) in palette.iter_mut().zip(DEFAULT_PALETTE.iter()) {
+        *color = rgb(*value);
+    }
+    palette
+}
+
+// Colors given on the command line replace the defaults from the start,
+// any left unspecified keep their default
+fn parse_palette(value: Option<&String>) -> [[u8; 3]; 16] {
+    let mut palette = default_palette();
+
+    let value = match value {
+        Some(value) => value,
+        None => usage(),
+    };
+
+    for (idx, hex) in value.split(',').enumerate() {
+        match (palette.get_mut(idx), u32::from_str_radix(hex.trim_start_matches('#'), 16)) {
+            (Some(color), Ok(value)) => *color = rgb(value),
+            _ => {
+                println!("Invalid palette entry '{}'", hex);
+                usage();
+            }
+        }
+    }
+    palette
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut rom_path = None;
+    #[cfg(feature = "sdl")]
+    let mut beeper = beeper::BeeperConfig::default();
+    let mut machine = chip8::Machine::Chip8;
+    let mut quirks = None;
+    let mut memory = None;
+    let mut load_addr = chip8::PROGRAM_START;
+    let mut palette = default_palette();
+    #[cfg(feature = "sdl")]
+    let mut rewind_seconds = 30;
+    #[cfg(feature = "sdl")]
+    let mut rewind_mib = 64;
+    #[cfg(feature = "sdl")]
+    let mut debug = false;
+    #[cfg(feature = "sdl")]
+    let mut gdb_port = None;
+    let mut trace_path = None;
+    let mut log_level = log::LevelFilter::Warn;
+    let mut headless = false;
+    let mut limit = None;
+    let mut keys = None;
+    let mut screen_path = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--machine" => machine = parse_value(arg, iter.next()),
+            "--quirks" => quirks = Some(parse_value(arg, iter.next())),
+            "--memory" => memory = Some(parse_value(arg, iter.next())),
+            "--load-addr" => load_addr = parse_addr(arg, iter.next()),
+            "--palette" => palette = parse_palette(iter.next()),
+            #[cfg(feature = "sdl")]
+            "--tone" => beeper.frequency = parse_value(arg, iter.next()),
+            #[cfg(feature = "sdl")]
+            "--waveform" => beeper.waveform = parse_value(arg, iter.next()),
+            #[cfg(feature = "sdl")]
+            "--volume" => {
+                let volume: u8 = parse_value(arg, iter.next());
+                beeper.volume = volume.min(100) as f32 / 100.0;
+            },
+            #[cfg(feature = "sdl")]
+            "--mute" => beeper.muted = true,
+            #[cfg(feature = "sdl")]
+            "--rewind" => rewind_seconds = parse_value(arg, iter.next()),
+            #[cfg(feature = "sdl")]
+            "--rewind-mem" => rewind_mib = parse_value(arg, iter.next()),
+            #[cfg(feature = "sdl")]
+            "--debug" => debug = true,
+            #[cfg(feature = "sdl")]
+            "--gdb" => gdb_port = Some(parse_value(arg, iter.next())),
+            "--trace" => trace_path = Some(parse_value(arg, iter.next())),
+            "--log" => {
+                let value: String = parse_value(arg, iter.next());
+                log_level = trace::parse_level(&value).unwrap_or_else(|| {
+                    println!("Invalid or missing value for {}", arg);
+                    usage();
+                });
+            },
+            "--headless" => headless = true,
+            "--cycles" => limit = Some(headless::Limit::Cycles(parse_value(arg, iter.next()))),
+            "--frames" => limit = Some(headless::Limit::Frames(parse_value(arg, iter.next()))),
+            "--keys" => keys = Some(parse_keys(iter.next())),
+            "--screen" => screen_path = Some(parse_value(arg, iter.next())),
+            "-h" | "--help" => usage(),
+            _ if arg.starts_with("--") => {
+                println!("Unknown option {}", arg);
+                usage();
+            },
+            _ => rom_path = Some(arg.clone()),
+        }
+    }
+
+    #[cfg(feature = "sdl")]
+    {
+        if debug && gdb_port.is_some() {
+            println!("--debug and --gdb cannot be used together");
+            usage();
+        }
+        if headless && (debug || gdb_port.is_some()) {
+            println!("--headless cannot be used with --debug or --gdb");
+            usage();
+        }
+    }
+    if headless {
+        if limit.is_none() {
+            println!("--headless needs --cycles or --frames");
+            usage();
+        }
+    } else if limit.is_some() || keys.is_some() || screen_path.is_some() {
+        println!("--cycles, --frames, --keys and --screen need --headless");
+        usage();
+    }
+
+    match rom_path {
+        Some(rom_path) => Options {
+            rom_path,
+            #[cfg(feature = "sdl")]
+            beeper,
+            machine,
+            quirks,
+            memory,
+            load_addr,
+            palette,
+            #[cfg(feature = "sdl")]
+            rewind_seconds,
+            #[cfg(feature = "sdl")]
+            rewind_mib,
+            #[cfg(feature = "sdl")]
+            debug,
+            #[cfg(feature = "sdl")]
+            gdb_port,
+            trace_path,
+            log_level,
+            headless,
+            limit,
+            keys: keys.unwrap_or_default(),
+            screen_path,
+        },
+        None => {
+            println!("Please provide a rom file to load");
+            usage();
+        }
+    }
+}
 
-fn draw_frame_buffer(canvas: &mut Canvas<sdl2::video::Window>, pixels: [u8; 256]) {
-    for x in 0..(chip8::SCREEN_X / 8) {
-        for y in 0..chip8::SCREEN_Y {
-            let bit_row = pixels[x * chip8::SCREEN_Y + y];
-            if bit_row == 0 {
-                continue;
+fn parse_keys(value: Option<&String>) -> Vec<headless::KeyEvent> {
+    let script = match value {
+        Some(value) if value.starts_with('@') => match std::fs::read_to_string(&value[1..]) {
+            Ok(script) => script,
+            Err(e) => {
+                eprintln!("Unable to read key script {}: {}", &value[1..], e);
+                process::exit(1);
             }
+        },
+        Some(value) => value.clone(),
+        None => {
+            println!("Invalid or missing value for --keys");
+            usage();
+        }
+    };
+    headless::parse_keys(&script).unwrap_or_else(|e| {
+        println!("Invalid --keys: {}", e);
+        usage();
+    })
+}
+
+fn read_rom(path: &str) -> Vec<u8> {
+    let mut contents: Vec<u8> = Vec::new();
+    if let Err(e) = File::open(path).and_then(|mut f| f.read_to_end(&mut contents)) {
+        eprintln!("Unable to read {}: {}", path, e);
+        process::exit(1);
+    }
+    contents
+}
+
+// --flow follows control flow from the entry point to separate code from
+// data and emits labelled, reassemblable output
+fn disasm_main(args: &[String]) {
+    let (flow, path) = match args {
+        [path] => (false, path),
+        [flag, path] if flag == "--flow" => (true, path),
+        _ => usage(),
+    };
 
-            for i in 0..8 {
-                let mask = 0x80 >> i;
-                if bit_row & mask > 0 {
-                    canvas.pixel((8 * x + i) as i16, y as i16, Color::RGB(0, 255, 0));
+    let rom = read_rom(path);
+    if flow {
+        print!("{}", disasm::flow_listing(&rom, 0x200));
+    } else {
+        print!("{}", disasm::listing(&rom, 0x200));
+    }
+}
+
+// Assemble or compile to the given output, or next to the source with a
+// .ch8 extension
+fn asm_main(args: &[String], build: fn(&str) -> Result<Vec<u8>, asm::AsmError>) {
+    let (source_path, out_path) = match args {
+        [source] => (source, Path::new(source).with_extension("ch8")),
+        [source, flag, out] | [flag, out, source] if flag == "-o" => (source, PathBuf::from(out)),
+        _ => usage(),
+    };
+
+    let mut source = String::new();
+    if let Err(e) = File::open(source_path).and_then(|mut f| f.read_to_string(&mut source)) {
+        eprintln!("Unable to read {}: {}", source_path, e);
+        process::exit(1);
+    }
+
+    match build(&source) {
+        Ok(rom) => {
+            if let Err(e) = File::create(&out_path).and_then(|mut f| f.write_all(&rom)) {
+                eprintln!("Unable to write {}: {}", out_path.display(), e);
+                process::exit(1);
+            }
+            println!("Wrote {} bytes to {}", rom.len(), out_path.display());
+        },
+        Err(e) => {
+            eprintln!("{}:{}", source_path, e);
+            process::exit(1);
+        }
+    }
+}
+
+// Run without SDL, print the registers then print or save the display.
+// Exits with an error status if the core faults.
+fn headless_main(core: &mut chip8::Core, options: &Options) -> ! {
+    let result = headless::run(core, options.limit.unwrap(), &options.keys, CYCLES_PER_FRAME);
+    // Dropping the tracer flushes it, process::exit won't
+    core.set_tracer(None);
+    print!("{}", headless::register_text(core));
+
+    match options.screen_path {
+        Some(ref path) => {
+            let saved = File::create(path).and_then(|mut f| {
+                if path.ends_with(".png") {
+                    headless::write_png(&mut f, core, &options.palette)
+                } else {
+                    f.write_all(headless::screen_text(core).as_bytes())
                 }
+            });
+            if let Err(e) = saved {
+                eprintln!("Unable to save display to {}: {}", path, e);
+                process::exit(1);
             }
+        },
+        None => print!("{}", headless::screen_text(core)),
+    }
+
+    match result {
+        Ok(()) => process::exit(0),
+        Err(e) => {
+            eprintln!("CORE FAULT: {}", e);
+            process::exit(1);
         }
     }
 }
 
 fn main() {
 
-    let args: Vec<_> = env::args().collect();
-    if args.len() < 2 {
-        println!("Please provide a rom file to load");
+    let args: Vec<_> = env::args().skip(1).collect();
+    match args.first().map(|a| a.as_str()) {
+        Some("disasm") => return disasm_main(&args[1..]),
+        Some("asm") => return asm_main(&args[1..], asm::assemble),
+        Some("octo") => return asm_main(&args[1..], octo::compile),
+        _ => {},
+    }
+
+    // "run" is optional, it only reads better in front of --headless
+    let args = if args.first().map(|a| a.as_str()) == Some("run") { &args[1..] } else { &args[..] };
+    let options = parse_args(args);
+    trace::init_logger(options.log_level);
+    let contents = read_rom(&options.rom_path);
+
+    let preset = options.quirks.unwrap_or(match options.machine {
+        chip8::Machine::Chip8 => quirks::Preset::CosmacVip,
+        chip8::Machine::SuperChip => quirks::Preset::SuperChip11,
+        chip8::Machine::XoChip => quirks::Preset::Octo,
+    });
+    info!("Using {} quirks", preset);
+    let memory = options.memory.unwrap_or(options.machine.memory_size());
+    let mut core = chip8::Core::with_memory(options.machine, quirks::Quirks::from_preset(preset),
+                                            memory);
+    if let Err(e) = core.load_rom_at(&contents, options.load_addr) {
+        eprintln!("Unable to load {}: {}", options.rom_path, e);
         process::exit(1);
     }
+    if let Some(ref path) = options.trace_path {
+        match trace::TraceFile::create(path) {
+            Ok(tracer) => core.set_tracer(Some(Box::new(tracer))),
+            Err(e) => {
+                eprintln!("Unable to create trace file {}: {}", path, e);
+                process::exit(1);
+            }
+        }
+    }
 
-    let mut f = File::open(&args[1]).expect("file not found");
+    if options.headless {
+        headless_main(&mut core, &options);
+    }
+    window_main(core, &options);
+}
 
-    let mut contents: Vec<u8> = Vec::new();
-    f.read_to_end(&mut contents)
-        .expect("something went wrong reading the file");
+#[cfg(not(feature = "sdl"))]
+fn window_main(_core: chip8::Core, _options: &Options) {
+    eprintln!("Built without the sdl feature, only --headless runs are available");
+    process::exit(1);
+}
 
-    let mut core = chip8::Core::new();
-    core.load_rom(&contents);
+// Run the core in an SDL window with sound until the window is closed
+#[cfg(feature = "sdl")]
+fn window_main(mut core: chip8::Core, options: &Options) {
+    let palette = options.palette.map(|[r, g, b]| Color::RGB(r, g, b));
 
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
+    // Headless servers and WSL often have no audio device, that's no
+    // reason not to run
+    let mut beeper = match sdl_context.audio()
+        .and_then(|audio| beeper::Beeper::new(&audio, options.beeper)) {
+        Ok(beeper) => Some(beeper),
+        Err(e) => {
+            warn!("Unable to open an audio device, running without sound: {}", e);
+            None
+        }
+    };
 
     let window = video_subsystem.window("CHIP_8 Emulator",
                                         SCREEN_WIDTH as u32,
@@ -65,19 +568,89 @@ fn main() {
         .unwrap();
 
     let mut canvas = window.into_canvas().build().unwrap();
+    let mut fault: Option<chip8::CoreError> = None;
+    let mut waiting_for_key = false;
+    let mut exited = false;
 
-    canvas.set_scale(SCALING_FACTOR as f32, SCALING_FACTOR as f32);
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
     canvas.present();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    let mut rewind = rewind::Rewind::new(options.rewind_seconds * chip8::TIMER_FREQ as usize,
+                                         options.rewind_mib * 1024 * 1024);
+    let mut rewinding = false;
+
+    let mut monitor: Option<Box<dyn Monitor>> = if let Some(port) = options.gdb_port {
+        match gdb::GdbStub::listen(port, CYCLES_PER_FRAME) {
+            Ok(stub) => Some(Box::new(stub)),
+            Err(e) => {
+                println!("Unable to listen for GDB on port {}: {}", port, e);
+                process::exit(1);
+            }
+        }
+    } else if options.debug {
+        Some(Box::new(debugger::Debugger::new(CYCLES_PER_FRAME)))
+    } else {
+        None
+    };
+
+    let frame_time = Duration::new(0, (1_000_000_000 / chip8::TIMER_FREQ) as u32);
+
     'running: loop {
+        // The window is left showing the last frame while at the prompt
+        if let Some(ref mut monitor) = monitor {
+            if monitor.paused() {
+                if !monitor.interact(&mut core) {
+                    break 'running;
+                }
+                // Resuming after a fault carries on from whatever state
+                // the user left the core in
+                if fault.take().is_some() {
+                    canvas.window_mut().set_title("CHIP_8 Emulator").unwrap();
+                }
+            }
+        }
+
+        let frame_start = Instant::now();
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit {..} | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'running
                 },
+                // Reset the core after a fault (or at any other time)
+                Event::KeyDown { keycode: Some(Keycode::Backspace), ..} => {
+                    core.soft_reset();
+                    fault = None;
+                    canvas.window_mut().set_title("CHIP_8 Emulator").unwrap();
+                },
+                Event::KeyDown { keycode: Some(keycode), keymod, repeat: false, .. }
+                    if state_slot(keycode).is_some() => {
+                    let slot = state_slot(keycode).unwrap();
+                    if keymod.intersects(LSHIFTMOD | RSHIFTMOD) {
+                        save_state(&core, &options.rom_path, slot);
+                    } else if load_state(&mut core, &options.rom_path, slot) {
+                        fault = None;
+                        canvas.window_mut().set_title("CHIP_8 Emulator").unwrap();
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::Tab), repeat: false, ..} => {
+                    println!("Rewinding ({:.1}s available)", rewind.seconds(chip8::TIMER_FREQ));
+                    rewinding = true;
+                },
+                Event::KeyUp { keycode: Some(Keycode::Tab), ..} => rewinding = false,
+                Event::KeyDown { keycode: Some(Keycode::F12), ..} => {
+                    if let Some(ref mut monitor) = monitor {
+                        monitor.pause();
+                    }
+                },
+                Event::KeyDown { keycode: Some(Keycode::M), ..} => {
+                    if let Some(ref mut beeper) = beeper {
+                        let muted = beeper.toggle_mute();
+                        println!("Sound {}", if muted { "muted" } else { "unmuted" });
+                    }
+                },
                 Event::KeyDown { keycode: Some(Keycode::Q), ..} => core.set_key(0),
                 Event::KeyDown { keycode: Some(Keycode::W), ..} => core.set_key(1),
                 Event::KeyDown { keycode: Some(Keycode::E), ..} => core.set_key(2),
@@ -113,14 +686,72 @@ fn main() {
                 _ => {}
             }
         }
-        ::std::thread::sleep(Duration::from_millis(1000/CORE_FREQ));
+        // While faulted the core stays paused so the last frame (and the
+        // fault itself) can be inspected until the user resets
+        if rewinding {
+            // Step back a frame at a time, sitting on the oldest one once
+            // the history runs out
+            if let Some(state) = rewind.pop() {
+                match core.load_state(&state) {
+                    Ok(()) => if fault.take().is_some() {
+                        canvas.window_mut().set_title("CHIP_8 Emulator").unwrap();
+                    },
+                    Err(e) => {
+                        warn!("Discarding the rewind history, it can't be restored: {}", e);
+                        rewind.clear();
+                        rewinding = false;
+                    }
+                }
+            }
+        } else if fault.is_none() {
+            let result = match monitor {
+                Some(ref mut monitor) => monitor.run_frame(&mut core),
+                None => core.run_frame(CYCLES_PER_FRAME),
+            };
+            if let Err(e) = result {
+                println!("CORE FAULT: {} (press Backspace to reset)", e);
+                let title = format!("CHIP_8 Emulator - FAULT: {}", e);
+                canvas.window_mut().set_title(&title).unwrap();
+                fault = Some(e);
+            } else if options.rewind_seconds > 0 {
+                rewind.push(core.save_state());
+            }
+        }
 
-        // The rest of the game loop goes here...
-        core.tick();
-        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        if core.has_exited() != exited {
+            exited = core.has_exited();
+            if exited {
+                println!("Program exited (press Backspace to restart)");
+                canvas.window_mut().set_title("CHIP_8 Emulator (exited)").unwrap();
+            }
+        }
+
+        // Let the user know the ROM is blocked on Fx0A
+        if fault.is_none() && core.waiting_for_key() != waiting_for_key {
+            waiting_for_key = core.waiting_for_key();
+            let title = if waiting_for_key {
+                "CHIP_8 Emulator (waiting for key)"
+            } else {
+                "CHIP_8 Emulator"
+            };
+            canvas.window_mut().set_title(title).unwrap();
+        }
+        if let Some(ref mut beeper) = beeper {
+            beeper.set_pattern(core.audio_pattern());
+            beeper.update(fault.is_none() && core.sound_active());
+        }
+
+        canvas.set_draw_color(palette[0]);
         canvas.clear();
-        draw_frame_buffer(& mut canvas, core.frame_buffer);
+        draw_frame_buffer(&mut canvas, &palette, core.frame_buffer(),
+                          core.resolution());
         canvas.present();
+
+        // Pace the loop so timers run at 60 Hz however long a frame took
+        let elapsed = frame_start.elapsed();
+        if elapsed < frame_time {
+            ::std::thread::sleep(frame_time - elapsed);
+        }
     }
 
 
@@ -0,0 +1,900 @@
+use asm::{AsmError, ORIGIN};
+use disasm::Instruction;
+use std::collections::HashMap;
+use std::f64::consts;
+
+// Octo programs may use the whole XO-CHIP address space
+const MAX_ADDR: usize = 0x10000;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    col: usize,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+// A use of a label before its definition, patched once the label is known.
+// Long references fill a whole 16 bit word, others the low 12 bits of an
+// instruction.
+struct Proto {
+    addr: usize,
+    long: bool,
+}
+
+// The right hand side of a comparison
+enum Operand {
+    Reg(u8),
+    Byte(u8),
+}
+
+struct Condition {
+    reg: u8,
+    op: Token,
+    rhs: Option<Operand>,
+}
+
+enum Branch {
+    Begin,
+    Else,
+}
+
+fn err<T>(token: &Token, message: String) -> Result<T, AsmError> {
+    Err(AsmError { line: token.line, column: token.col, message })
+}
+
+// Split source into whitespace separated tokens. Braces and parentheses
+// always stand alone and '#' starts a comment.
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+
+    for (idx, text) in source.lines().enumerate() {
+        let line = idx + 1;
+        let mut cur = String::new();
+        let mut start = 0;
+
+        for (idx, c) in text.chars().enumerate() {
+            let col = idx + 1;
+            if c == '#' && cur.is_empty() {
+                break;
+            }
+
+            if c.is_whitespace() || "{}()".contains(c) {
+                if !cur.is_empty() {
+                    tokens.push(Token { text: cur.clone(), line, col: start });
+                    cur.clear();
+                }
+                if !c.is_whitespace() {
+                    tokens.push(Token { text: c.to_string(), line, col });
+                }
+            } else {
+                if cur.is_empty() {
+                    start = col;
+                }
+                cur.push(c);
+            }
+        }
+
+        if !cur.is_empty() {
+            tokens.push(Token { text: cur, line, col: start });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit())
+        && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+    {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    if text.len() == 2 && (text.starts_with('v') || text.starts_with('V')) {
+        u8::from_str_radix(&text[1..], 16).ok()
+    } else {
+        None
+    }
+}
+
+const KEYWORDS: &[&str] = &[
+    ":=", "|=", "&=", "^=", "-=", "=-", "+=", ">>=", "<<=", "==", "!=", "<", ">", "<=", ">=",
+    "key", "-key", "hex", "bighex", "random", "delay", "buzzer", "pitch", "long", "i", ";",
+    "return", "clear", "bcd", "save", "load", "saveflags", "loadflags", "sprite", "jump",
+    "jump0", "native", "if", "then", "begin", "else", "end", "loop", "while", "again",
+    "hires", "lores", "exit", "scroll-down", "scroll-up", "scroll-right", "scroll-left",
+    "audio", "plane",
+];
+
+fn is_reserved(text: &str) -> bool {
+    parse_register(text).is_some() || KEYWORDS.contains(&text)
+}
+
+fn truncate(value: f64) -> i64 {
+    value as i64
+}
+
+fn unary(op: &str, value: f64) -> Option<f64> {
+    Some(match op {
+        "-" => -value,
+        "~" => !truncate(value) as f64,
+        "!" => if value == 0.0 { 1.0 } else { 0.0 },
+        "sin" => value.sin(),
+        "cos" => value.cos(),
+        "tan" => value.tan(),
+        "exp" => value.exp(),
+        "log" => value.ln(),
+        "abs" => value.abs(),
+        "sqrt" => value.sqrt(),
+        "sign" => if value == 0.0 { 0.0 } else { value.signum() },
+        "ceil" => value.ceil(),
+        "floor" => value.floor(),
+        _ => return None,
+    })
+}
+
+fn binary(op: &str, a: f64, b: f64) -> Option<f64> {
+    let flag = |cond: bool| if cond { 1.0 } else { 0.0 };
+    Some(match op {
+        "-" => a - b,
+        "+" => a + b,
+        "*" => a * b,
+        "/" => a / b,
+        "%" => a % b,
+        "&" => (truncate(a) & truncate(b)) as f64,
+        "|" => (truncate(a) | truncate(b)) as f64,
+        "^" => (truncate(a) ^ truncate(b)) as f64,
+        "<<" => (truncate(a) << (truncate(b) & 0x3f)) as f64,
+        ">>" => (truncate(a) >> (truncate(b) & 0x3f)) as f64,
+        "pow" => a.powf(b),
+        "min" => a.min(b),
+        "max" => a.max(b),
+        "<" => flag(a < b),
+        "<=" => flag(a <= b),
+        "==" => flag(a == b),
+        "!=" => flag(a != b),
+        ">=" => flag(a >= b),
+        ">" => flag(a > b),
+        _ => return None,
+    })
+}
+
+struct Compiler {
+    // Upcoming tokens, last first so macros can be expanded by pushing
+    tokens: Vec<Token>,
+    last: Token,
+    rom: Vec<u8>,
+    written: Vec<bool>,
+    here: usize,
+    has_main: bool,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    protos: HashMap<String, (Token, Vec<Proto>)>,
+    loops: Vec<(usize, Token, Vec<usize>)>,
+    branches: Vec<(usize, Token, Branch)>,
+}
+
+impl Compiler {
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop() {
+            Some(token) => {
+                self.last = token.clone();
+                Ok(token)
+            },
+            None => err(&self.last, "unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|t| t.text.as_str())
+    }
+
+    fn expect(&mut self, text: &str) -> Result<Token, AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return err(&token, format!("expected '{}', got '{}'", text, token.text));
+        }
+        Ok(token)
+    }
+
+    fn register_of(&self, text: &str) -> Option<u8> {
+        parse_register(text).or_else(|| self.aliases.get(text).cloned())
+    }
+
+    fn is_register(&self) -> bool {
+        self.tokens.last().is_some_and(|t| self.register_of(&t.text).is_some())
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        match self.register_of(&token.text) {
+            Some(reg) => Ok(reg),
+            None => err(&token, format!("expected a register, got '{}'", token.text)),
+        }
+    }
+
+    // Make sure a new label, constant, alias or macro name is usable
+    fn check_name(&self, token: &Token, what: &str) -> Result<(), AsmError> {
+        let name = &token.text;
+        if is_reserved(name) || parse_number(name).is_some() || name.starts_with(':') {
+            return err(token, format!("'{}' cannot be used as a {} name", name, what));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name)
+            || self.aliases.contains_key(name) || self.macros.contains_key(name)
+        {
+            return err(token, format!("'{}' is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, token: &Token, byte: u8) -> Result<(), AsmError> {
+        if self.here >= MAX_ADDR {
+            return err(token, "program does not fit in memory".to_string());
+        }
+        let idx = self.here - ORIGIN;
+        if idx >= self.rom.len() {
+            self.rom.resize(idx + 1, 0);
+            self.written.resize(idx + 1, false);
+        }
+        if self.written[idx] {
+            return err(token, format!("data overlap at address 0x{:04X}", self.here));
+        }
+        self.rom[idx] = byte;
+        self.written[idx] = true;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, token: &Token, inst: Instruction) -> Result<(), AsmError> {
+        for byte in inst.encode() {
+            self.emit_byte(token, byte)?;
+        }
+        Ok(())
+    }
+
+    // Point the jump at `addr` to `target`
+    fn patch_jump(&mut self, token: &Token, addr: usize, target: usize) -> Result<(), AsmError> {
+        if target > 0xfff {
+            return err(token, format!("'{}' jumps to 0x{:04X}, out of reach of a 12 bit address", token.text, target));
+        }
+        let idx = addr - ORIGIN;
+        self.rom[idx] = 0x10 | (target >> 8) as u8;
+        self.rom[idx + 1] = target as u8;
+        Ok(())
+    }
+
+    fn define_label(&mut self, token: &Token, addr: usize) -> Result<(), AsmError> {
+        self.check_name(token, "label")?;
+        self.labels.insert(token.text.clone(), addr);
+
+        if let Some((_, protos)) = self.protos.remove(&token.text) {
+            for proto in protos {
+                let idx = proto.addr - ORIGIN;
+                if proto.long {
+                    self.rom[idx] = (addr >> 8) as u8;
+                    self.rom[idx + 1] = addr as u8;
+                } else if addr > 0xfff {
+                    return err(token, format!("label '{}' at 0x{:04X} is out of reach of a 12 bit address", token.text, addr));
+                } else {
+                    self.rom[idx] = (self.rom[idx] & 0xf0) | (addr >> 8) as u8;
+                    self.rom[idx + 1] = addr as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Look up a number, constant or label
+    fn lookup(&self, text: &str) -> Option<f64> {
+        parse_number(text)
+            .or_else(|| self.constants.get(text).cloned())
+            .or_else(|| self.labels.get(text).map(|addr| *addr as f64))
+    }
+
+    fn calc_term(&mut self) -> Result<f64, AsmError> {
+        let token = self.next()?;
+        if token.text == "(" {
+            let value = self.calc_expr()?;
+            self.expect(")")?;
+            return Ok(value);
+        }
+        if token.text == "@" {
+            let addr = truncate(self.calc_term()?);
+            let idx = addr - ORIGIN as i64;
+            return Ok(if idx >= 0 { self.rom.get(idx as usize).map_or(0.0, |b| *b as f64) } else { 0.0 });
+        }
+        if let Some(op) = unary(&token.text, 0.0).map(|_| token.text.clone()) {
+            let value = self.calc_term()?;
+            return Ok(unary(&op, value).unwrap());
+        }
+
+        match token.text.as_str() {
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(consts::PI),
+            "E" => Ok(consts::E),
+            text => match self.lookup(text) {
+                Some(value) => Ok(value),
+                None if self.protos.contains_key(text) =>
+                    err(&token, format!("cannot use forward reference '{}' in a calculation", text)),
+                None => err(&token, format!("undefined name '{}'", text)),
+            },
+        }
+    }
+
+    // Octo expressions have no precedence and evaluate right to left
+    fn calc_expr(&mut self) -> Result<f64, AsmError> {
+        let lhs = self.calc_term()?;
+        match self.peek() {
+            Some(")") | Some("}") | None => return Ok(lhs),
+            _ => {},
+        }
+
+        let op = self.next()?;
+        let rhs = self.calc_expr()?;
+        match binary(&op.text, lhs, rhs) {
+            Some(value) => Ok(value),
+            None => err(&op, format!("unknown operator '{}'", op.text)),
+        }
+    }
+
+    // A value known right now: a number, constant, label or { calculation }
+    fn constant(&mut self) -> Result<(Token, i64), AsmError> {
+        let token = self.next()?;
+        let value = if token.text == "{" {
+            let value = self.calc_expr()?;
+            self.expect("}")?;
+            value
+        } else {
+            match self.lookup(&token.text) {
+                Some(value) => value,
+                None => return err(&token, format!("undefined name '{}'", token.text)),
+            }
+        };
+        Ok((token, value.floor() as i64))
+    }
+
+    fn ranged(&mut self, min: i64, max: i64, what: &str) -> Result<i64, AsmError> {
+        let (token, value) = self.constant()?;
+        if value < min || value > max {
+            return err(&token, format!("{} {} out of range ({}..{})", what, value, min, max));
+        }
+        Ok(value)
+    }
+
+    fn byte(&mut self) -> Result<u8, AsmError> {
+        self.ranged(-128, 0xff, "byte").map(|v| v as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, AsmError> {
+        self.ranged(0, 0xf, "value").map(|v| v as u8)
+    }
+
+    // A 12 bit address for the instruction about to be emitted, which may
+    // refer to a label defined further on
+    fn address(&mut self) -> Result<u16, AsmError> {
+        let is_proto = match self.tokens.last() {
+            Some(token) => token.text != "{" && self.lookup(&token.text).is_none(),
+            None => false,
+        };
+        if !is_proto {
+            return self.ranged(0, 0xfff, "address").map(|v| v as u16);
+        }
+
+        let token = self.next()?;
+        self.forward(token, false, self.here)?;
+        Ok(0)
+    }
+
+    fn forward(&mut self, token: Token, long: bool, addr: usize) -> Result<(), AsmError> {
+        if is_reserved(&token.text) || token.text.starts_with(':') {
+            return err(&token, format!("expected an address, got '{}'", token.text));
+        }
+        self.protos.entry(token.text.clone()).or_insert((token, Vec::new())).1.push(Proto { addr, long });
+        Ok(())
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let reg = self.register()?;
+        let op = self.next()?;
+        let rhs = match op.text.as_str() {
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(if self.is_register() {
+                Operand::Reg(self.register()?)
+            } else {
+                Operand::Byte(self.byte()?)
+            }),
+            _ => return err(&op, format!("'{}' is not a comparison", op.text)),
+        };
+        Ok(Condition { reg, op, rhs })
+    }
+
+    // Emit code that skips the next instruction when the condition is false,
+    // or when it is true if `negated`
+    fn skip_unless(&mut self, cond: &Condition, negated: bool) -> Result<(), AsmError> {
+        use self::Instruction::*;
+
+        let op = match (cond.op.text.as_str(), negated) {
+            ("==", true) => "!=",
+            ("!=", true) => "==",
+            ("key", true) => "-key",
+            ("-key", true) => "key",
+            ("<", true) => ">=",
+            (">", true) => "<=",
+            ("<=", true) => ">",
+            (">=", true) => "<",
+            (op, _) => op,
+        };
+
+        let x = cond.reg;
+        let tmp = self.aliases["compare-temp"];
+        let token = &cond.op;
+        match (op, &cond.rhs) {
+            ("==", &Some(Operand::Reg(y))) => self.emit(token, SneReg(x, y)),
+            ("==", &Some(Operand::Byte(kk))) => self.emit(token, SneByte(x, kk)),
+            ("!=", &Some(Operand::Reg(y))) => self.emit(token, SeReg(x, y)),
+            ("!=", &Some(Operand::Byte(kk))) => self.emit(token, SeByte(x, kk)),
+            ("key", _) => self.emit(token, Sknp(x)),
+            ("-key", _) => self.emit(token, Skp(x)),
+            (op, rhs) => {
+                // Compare through a subtraction into the temporary register
+                match *rhs {
+                    Some(Operand::Reg(y)) => self.emit(token, LdReg(tmp, y))?,
+                    Some(Operand::Byte(kk)) => self.emit(token, LdByte(tmp, kk))?,
+                    None => unreachable!(),
+                }
+                let sub = if op == ">" || op == "<=" { Sub(tmp, x) } else { Subn(tmp, x) };
+                self.emit(token, sub)?;
+                if op == ">" || op == "<" {
+                    self.emit(token, SeByte(0xf, 1))
+                } else {
+                    self.emit(token, SneByte(0xf, 1))
+                }
+            },
+        }
+    }
+
+    fn assignment(&mut self, x: u8) -> Result<(), AsmError> {
+        use self::Instruction::*;
+
+        let op = self.next()?;
+        let reg = self.is_register();
+        let inst = match (op.text.as_str(), reg) {
+            (":=", true) => LdReg(x, self.register()?),
+            (":=", false) => match self.peek() {
+                Some("random") => {
+                    self.next()?;
+                    Rnd(x, self.byte()?)
+                },
+                Some("key") => {
+                    self.next()?;
+                    LdRegKey(x)
+                },
+                Some("delay") => {
+                    self.next()?;
+                    LdRegDt(x)
+                },
+                _ => LdByte(x, self.byte()?),
+            },
+            ("+=", true) => AddReg(x, self.register()?),
+            ("+=", false) => AddByte(x, self.byte()?),
+            ("-=", true) => Sub(x, self.register()?),
+            ("-=", false) => AddByte(x, self.byte()?.wrapping_neg()),
+            ("=-", true) => Subn(x, self.register()?),
+            ("|=", true) => Or(x, self.register()?),
+            ("&=", true) => And(x, self.register()?),
+            ("^=", true) => Xor(x, self.register()?),
+            (">>=", true) => Shr(x, self.register()?),
+            ("<<=", true) => Shl(x, self.register()?),
+            ("=-", false) | ("|=", false) | ("&=", false) | ("^=", false) | (">>=", false)
+            | ("<<=", false) => {
+                let token = self.next()?;
+                return err(&token, format!("expected a register, got '{}'", token.text));
+            },
+            _ => return err(&op, format!("unknown operator '{}'", op.text)),
+        };
+        self.emit(&op, inst)
+    }
+
+    fn index(&mut self, token: &Token) -> Result<(), AsmError> {
+        use self::Instruction::*;
+
+        let op = self.next()?;
+        match op.text.as_str() {
+            "+=" => {
+                let x = self.register()?;
+                self.emit(token, AddIReg(x))
+            },
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(token, LdF(x))
+                },
+                Some("bighex") => {
+                    self.next()?;
+                    let x = self.register()?;
+                    self.emit(token, LdHf(x))
+                },
+                Some("long") => {
+                    self.next()?;
+                    let known = self.tokens.last()
+                        .is_some_and(|t| t.text == "{" || self.lookup(&t.text).is_some());
+                    let addr = if known {
+                        self.ranged(0, 0xffff, "address")? as u16
+                    } else {
+                        let label = self.next()?;
+                        let here = self.here;
+                        self.forward(label, true, here + 2)?;
+                        0
+                    };
+                    self.emit(token, LdILong(addr))
+                },
+                _ => {
+                    let addr = self.address()?;
+                    self.emit(token, LdI(addr))
+                },
+            },
+            _ => err(&op, format!("unknown operator '{}'", op.text)),
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        use self::Instruction::*;
+
+        if let Some(x) = self.register_of(&token.text) {
+            return self.assignment(x);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                if name.text == "main" && self.has_main && self.here == ORIGIN + 2 {
+                    // Main comes first, no need to jump to it
+                    self.has_main = false;
+                    self.here = ORIGIN;
+                    self.rom.clear();
+                    self.written.clear();
+                }
+                let here = self.here;
+                self.define_label(&name, here)?;
+            },
+            ":next" => {
+                let name = self.next()?;
+                let here = self.here;
+                self.define_label(&name, here + 1)?;
+            },
+            ":alias" => {
+                let name = self.next()?;
+                if !self.aliases.contains_key(&name.text) {
+                    self.check_name(&name, "alias")?;
+                }
+                let reg = self.register()?;
+                self.aliases.insert(name.text, reg);
+            },
+            ":const" => {
+                let name = self.next()?;
+                self.check_name(&name, "constant")?;
+                let (_, value) = self.constant()?;
+                self.constants.insert(name.text, value as f64);
+            },
+            ":calc" => {
+                // Unlike :const this may redefine an existing constant
+                let name = self.next()?;
+                if !self.constants.contains_key(&name.text) {
+                    self.check_name(&name, "constant")?;
+                }
+                self.expect("{")?;
+                let value = self.calc_expr()?;
+                self.expect("}")?;
+                self.constants.insert(name.text, value);
+            },
+            ":macro" => {
+                let name = self.next()?;
+                self.check_name(&name, "macro")?;
+                let mut args = Vec::new();
+                loop {
+                    let arg = self.next()?;
+                    if arg.text == "{" {
+                        break;
+                    }
+                    args.push(arg.text);
+                }
+
+                let mut body = Vec::new();
+                let mut depth = 1;
+                loop {
+                    let token = self.next()?;
+                    match token.text.as_str() {
+                        "{" => depth += 1,
+                        "}" => depth -= 1,
+                        _ => {},
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    body.push(token);
+                }
+                self.macros.insert(name.text, Macro { args, body, calls: 0 });
+            },
+            ":org" => {
+                let addr = self.ranged(ORIGIN as i64, MAX_ADDR as i64 - 1, "address")?;
+                self.here = addr as usize;
+            },
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(&token, byte)?;
+            },
+            ":call" => {
+                let addr = self.address()?;
+                self.emit(&token, Call(addr))?;
+            },
+            ":unpack" => {
+                let hi = self.aliases["unpack-hi"];
+                let lo = self.aliases["unpack-lo"];
+                let (hi_byte, lo_byte) = if self.peek() == Some("long") {
+                    self.next()?;
+                    let addr = self.ranged(0, 0xffff, "address")?;
+                    ((addr >> 8) as u8, addr as u8)
+                } else {
+                    let nibble = self.nibble()?;
+                    let addr = self.ranged(0, 0xfff, "address")?;
+                    (nibble << 4 | (addr >> 8) as u8, addr as u8)
+                };
+                self.emit(&token, LdByte(hi, hi_byte))?;
+                self.emit(&token, LdByte(lo, lo_byte))?;
+            },
+            // Debugger annotations have no effect on the rom
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+            },
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            },
+            "clear" => self.emit(&token, Cls)?,
+            "return" | ";" => self.emit(&token, Ret)?,
+            "hires" => self.emit(&token, High)?,
+            "lores" => self.emit(&token, Low)?,
+            "exit" => self.emit(&token, Exit)?,
+            "scroll-right" => self.emit(&token, Scr)?,
+            "scroll-left" => self.emit(&token, Scl)?,
+            "audio" => self.emit(&token, Audio)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(&token, Scd(n))?;
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(&token, Scu(n))?;
+            },
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(&token, Plane(n))?;
+            },
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(&token, LdB(x))?;
+            },
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(&token, LdRReg(x))?;
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(&token, LdRegR(x))?;
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                let inst = if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    if token.text == "save" { LdRangeMem(x, y) } else { LdMemRange(x, y) }
+                } else if token.text == "save" {
+                    LdMemReg(x)
+                } else {
+                    LdRegMem(x)
+                };
+                self.emit(&token, inst)?;
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(&token, Drw(x, y, n))?;
+            },
+            "jump" => {
+                let addr = self.address()?;
+                self.emit(&token, Jp(addr))?;
+            },
+            "jump0" => {
+                let addr = self.address()?;
+                self.emit(&token, JpOffset(addr))?;
+            },
+            "native" => {
+                let addr = self.address()?;
+                self.emit(&token, Sys(addr))?;
+            },
+            "i" => self.index(&token)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let inst = match token.text.as_str() {
+                    "delay" => LdDtReg(x),
+                    "buzzer" => LdStReg(x),
+                    _ => Pitch(x),
+                };
+                self.emit(&token, inst)?;
+            },
+            "if" => {
+                let cond = self.condition()?;
+                let then = self.next()?;
+                match then.text.as_str() {
+                    "then" => self.skip_unless(&cond, false)?,
+                    "begin" => {
+                        self.skip_unless(&cond, true)?;
+                        self.branches.push((self.here, token.clone(), Branch::Begin));
+                        self.emit(&token, Jp(0))?;
+                    },
+                    _ => return err(&then, format!("expected 'then' or 'begin', got '{}'", then.text)),
+                }
+            },
+            "else" => {
+                match self.branches.pop() {
+                    Some((addr, _, Branch::Begin)) => {
+                        let target = self.here + 2;
+                        self.patch_jump(&token, addr, target)?;
+                    },
+                    _ => return err(&token, "'else' without a matching 'begin'".to_string()),
+                }
+                self.branches.push((self.here, token.clone(), Branch::Else));
+                self.emit(&token, Jp(0))?;
+            },
+            "end" => match self.branches.pop() {
+                Some((addr, _, _)) => {
+                    let here = self.here;
+                    self.patch_jump(&token, addr, here)?;
+                },
+                None => return err(&token, "'end' without a matching 'begin'".to_string()),
+            },
+            "loop" => self.loops.push((self.here, token.clone(), Vec::new())),
+            "while" => {
+                if self.loops.is_empty() {
+                    return err(&token, "'while' outside of a loop".to_string());
+                }
+                let cond = self.condition()?;
+                self.skip_unless(&cond, true)?;
+                let here = self.here;
+                self.loops.last_mut().unwrap().2.push(here);
+                self.emit(&token, Jp(0))?;
+            },
+            "again" => {
+                let (start, _, whiles) = match self.loops.pop() {
+                    Some(block) => block,
+                    None => return err(&token, "'again' without a matching 'loop'".to_string()),
+                };
+                if start > 0xfff {
+                    return err(&token, format!("loop at 0x{:04X} is out of reach of a jump", start));
+                }
+                self.emit(&token, Jp(start as u16))?;
+                let here = self.here;
+                for addr in whiles {
+                    self.patch_jump(&token, addr, here)?;
+                }
+            },
+            _ if self.macros.contains_key(&token.text) => self.expand(&token)?,
+            // Bare numbers are data
+            text if parse_number(text).is_some() => {
+                self.tokens.push(token.clone());
+                let byte = self.byte()?;
+                self.emit_byte(&token, byte)?;
+            },
+            text if text.starts_with(':') => {
+                return err(&token, format!("unknown directive '{}'", text));
+            },
+            _ => {
+                // Anything else is a subroutine call
+                self.tokens.push(token.clone());
+                let addr = self.address()?;
+                self.emit(&token, Call(addr))?;
+            },
+        }
+        Ok(())
+    }
+
+    // Substitute a macro's arguments into its body and queue the result
+    fn expand(&mut self, token: &Token) -> Result<(), AsmError> {
+        let count = self.macros[&token.text].args.len();
+        let mut values = HashMap::new();
+        for idx in 0..count {
+            let value = self.next()?;
+            values.insert(self.macros[&token.text].args[idx].clone(), value.text);
+        }
+
+        let mac = self.macros.get_mut(&token.text).unwrap();
+        values.insert("CALLS".to_string(), mac.calls.to_string());
+        mac.calls += 1;
+
+        for body in mac.body.iter().rev() {
+            let text = values.get(&body.text).cloned().unwrap_or_else(|| body.text.clone());
+            self.tokens.push(Token { text, line: body.line, col: body.col });
+        }
+        Ok(())
+    }
+}
+
+/// Compile Octo (.8o) source into a rom to be loaded at `ORIGIN`, matching
+/// the output of the Octo compiler byte for byte
+pub fn compile(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut tokens = tokenize(source);
+    tokens.reverse();
+
+    let mut aliases = HashMap::new();
+    aliases.insert("compare-temp".to_string(), 0xf);
+    aliases.insert("unpack-hi".to_string(), 0x0);
+    aliases.insert("unpack-lo".to_string(), 0x1);
+
+    // Octo leaves room for a jump to main at the start of the rom
+    let mut octo = Compiler {
+        tokens,
+        last: Token { text: String::new(), line: 1, col: 1 },
+        rom: vec![0, 0],
+        written: vec![true, true],
+        here: ORIGIN + 2,
+        has_main: true,
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        aliases,
+        macros: HashMap::new(),
+        protos: HashMap::new(),
+        loops: Vec::new(),
+        branches: Vec::new(),
+    };
+
+    while !octo.tokens.is_empty() {
+        let token = octo.next()?;
+        octo.statement(token)?;
+    }
+
+    if let Some((_, (token, _))) = octo.protos.iter().min_by_key(|p| ((p.1).0.line, (p.1).0.col)) {
+        return err(token, format!("undefined name '{}'", token.text));
+    }
+    if let Some((_, token, _)) = octo.loops.last() {
+        return err(token, "'loop' without a matching 'again'".to_string());
+    }
+    if let Some((_, token, _)) = octo.branches.last() {
+        return err(token, "'begin' without a matching 'end'".to_string());
+    }
+
+    if octo.has_main {
+        let main = match octo.labels.get("main") {
+            Some(main) => *main,
+            None => return err(&octo.last, "program is missing a 'main' label".to_string()),
+        };
+        if main > 0xfff {
+            return err(&octo.last, format!("main at 0x{:04X} is out of reach of a jump", main));
+        }
+        let last = octo.last.clone();
+        octo.patch_jump(&last, ORIGIN, main)?;
+    }
+    Ok(octo.rom)
+}
@@ -0,0 +1,126 @@
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+/// What FX55/FX65 leave in I once the registers have been transferred
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left untouched (SUPER-CHIP)
+    Unchanged,
+    /// I is advanced by X (CHIP-48)
+    ByX,
+    /// I is advanced past the last register, X + 1 (COSMAC VIP, Octo)
+    ByXPlusOne,
+}
+
+/// Behaviors of the ambiguous CHIP-8 instructions. These differ between
+/// interpreters and ROMs are usually only correct on the one they were
+/// written for, so each is a separate toggle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY and store the result in VX, rather than shifting
+    /// VX in place
+    pub shift_vy: bool,
+    /// Effect of FX55/FX65 on I
+    pub load_store_index: IndexIncrement,
+    /// BNNN is treated as BXNN, jumping to XNN + VX instead of NNN + V0
+    pub jump_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    /// DXYN clips sprites at the edges of the screen instead of wrapping
+    /// them around to the other side
+    pub clip_sprites: bool,
+}
+
+/// Named quirk presets matching well known interpreters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+    CosmacVip,
+    Chip48,
+    SuperChip11,
+    Octo,
+}
+
+impl Quirks {
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_index: IndexIncrement::ByXPlusOne,
+            jump_vx: false,
+            vf_reset: true,
+            clip_sprites: true,
+        }
+    }
+
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_index: IndexIncrement::ByX,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+        }
+    }
+
+    pub fn super_chip() -> Quirks {
+        Quirks {
+            shift_vy: false,
+            load_store_index: IndexIncrement::Unchanged,
+            jump_vx: true,
+            vf_reset: false,
+            clip_sprites: true,
+        }
+    }
+
+    /// Modern interpreters, following Octo's XO-CHIP behavior
+    pub fn octo() -> Quirks {
+        Quirks {
+            shift_vy: true,
+            load_store_index: IndexIncrement::ByXPlusOne,
+            jump_vx: false,
+            vf_reset: false,
+            clip_sprites: false,
+        }
+    }
+
+    pub fn from_preset(preset: Preset) -> Quirks {
+        match preset {
+            Preset::CosmacVip => Quirks::cosmac_vip(),
+            Preset::Chip48 => Quirks::chip48(),
+            Preset::SuperChip11 => Quirks::super_chip(),
+            Preset::Octo => Quirks::octo(),
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::cosmac_vip()
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Preset, String> {
+        match s {
+            "vip" | "cosmac-vip" | "chip8" => Ok(Preset::CosmacVip),
+            "chip48" => Ok(Preset::Chip48),
+            "schip" | "superchip" => Ok(Preset::SuperChip11),
+            "octo" | "modern" | "xochip" => Ok(Preset::Octo),
+            _ => Err(format!("unknown quirks preset '{}' (vip, chip48, schip, octo)", s)),
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            Preset::CosmacVip => "COSMAC VIP",
+            Preset::Chip48 => "CHIP-48",
+            Preset::SuperChip11 => "SUPER-CHIP 1.1",
+            Preset::Octo => "Octo",
+        };
+        write!(f, "{}", name)
+    }
+}
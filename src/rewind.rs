@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+/// Ring buffer of per-frame save states used to play the game backwards.
+///
+/// Only the newest snapshot is kept in full. Every older frame is stored as
+/// the XOR against the frame after it, run length encoded, so frames where
+/// little changes cost a handful of bytes.
+pub struct Rewind {
+    last: Option<Vec<u8>>,
+    deltas: VecDeque<Vec<u8>>,
+    bytes: usize,
+    max_frames: usize,
+    max_bytes: usize,
+}
+
+// Append a LEB128 style variable length integer
+fn push_varint(out: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        out.push((val as u8) | 0x80);
+        val >>= 7;
+    }
+    out.push(val as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    while let Some(byte) = data.get(*pos) {
+        *pos += 1;
+        val |= ((byte & 0x7f) as usize).checked_shl(shift).unwrap_or(0);
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    val
+}
+
+// Encode `cur ^ prev` as a series of (unchanged run, changed run, changed
+// bytes) records
+fn encode_delta(prev: &[u8], cur: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut pos = 0;
+
+    while pos < cur.len() {
+        let start = pos;
+        while pos < cur.len() && prev[pos] == cur[pos] {
+            pos += 1;
+        }
+        let skip = pos - start;
+
+        let start = pos;
+        while pos < cur.len() && prev[pos] != cur[pos] {
+            pos += 1;
+        }
+
+        push_varint(&mut out, skip);
+        push_varint(&mut out, pos - start);
+        out.extend(prev[start..pos].iter().zip(&cur[start..pos]).map(|(a, b)| a ^ b));
+    }
+    out
+}
+
+// Apply a delta produced by encode_delta in place. XOR is its own inverse
+// so this works in both directions. Returns false if the delta runs past
+// the end of the state or of itself.
+fn apply_delta(state: &mut [u8], delta: &[u8]) -> bool {
+    let mut pos = 0;
+    let mut idx = 0usize;
+
+    while pos < delta.len() {
+        idx = idx.saturating_add(read_varint(delta, &mut pos));
+        let len = read_varint(delta, &mut pos);
+        let bytes = idx.checked_add(len).and_then(|end| state.get_mut(idx..end));
+        let diffs = pos.checked_add(len).and_then(|end| delta.get(pos..end));
+        match (bytes, diffs) {
+            (Some(bytes), Some(diffs)) => {
+                for (byte, diff) in bytes.iter_mut().zip(diffs) {
+                    *byte ^= *diff;
+                }
+            },
+            _ => return false,
+        }
+        idx += len;
+        pos += len;
+    }
+    true
+}
+
+impl Rewind {
+    /// Keep up to `max_frames` frames of history, using no more than
+    /// roughly `max_bytes` of memory for it.
+    pub fn new(max_frames: usize, max_bytes: usize) -> Rewind {
+        Rewind {
+            last: None,
+            deltas: VecDeque::new(),
+            bytes: 0,
+            max_frames,
+            max_bytes,
+        }
+    }
+
+    /// Record the state at the end of a frame
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(last) = self.last.take() {
+            if last.len() == state.len() {
+                let delta = encode_delta(&state, &last);
+                self.bytes += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                self.deltas.clear();
+                self.bytes = 0;
+            }
+        }
+        self.last = Some(state);
+
+        while self.deltas.len() > self.max_frames
+            || (self.bytes > self.max_bytes && !self.deltas.is_empty())
+        {
+            if let Some(delta) = self.deltas.pop_front() {
+                self.bytes -= delta.len();
+            }
+        }
+    }
+
+    /// Step back one frame, returning the state to restore. Returns None
+    /// once the oldest recorded frame has been reached, or if the history
+    /// turns out to be damaged, in which case it's discarded.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let delta = self.deltas.pop_back()?;
+        self.bytes -= delta.len();
+
+        let state = self.last.as_mut()?;
+        if !apply_delta(state, &delta) {
+            self.clear();
+            return None;
+        }
+        Some(state.clone())
+    }
+
+    /// Throw away all of the history
+    pub fn clear(&mut self) {
+        self.last = None;
+        self.deltas.clear();
+        self.bytes = 0;
+    }
+
+    /// Seconds of history currently available at the given frame rate
+    pub fn seconds(&self, fps: u64) -> f32 {
+        self.deltas.len() as f32 / fps as f32
+    }
+}
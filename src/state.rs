@@ -0,0 +1,186 @@
+use alloc::vec::Vec;
+use core::error;
+use core::fmt;
+
+// Save state layout, all values little endian:
+//
+//   magic    [u8; 4]  "C8RS"
+//   version  u16
+//   machine  u8
+//   reserved u8
+//   length   u32      length of the payload
+//   checksum u32      CRC-32 of the payload
+//   payload  [u8; length]
+const MAGIC: &[u8; 4] = b"C8RS";
+pub const VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    MachineMismatch,
+    Truncated,
+    ChecksumMismatch,
+    Corrupt,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::BadMagic => write!(f, "not a chip8r save state"),
+            StateError::UnsupportedVersion(v) =>
+                write!(f, "unsupported save state version {} (expected {})", v, VERSION),
+            StateError::MachineMismatch =>
+                write!(f, "save state is for a different machine type"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::ChecksumMismatch => write!(f, "save state checksum mismatch"),
+            StateError::Corrupt => write!(f, "save state contents are invalid"),
+        }
+    }
+}
+
+impl error::Error for StateError {}
+
+/// CRC-32 (IEEE 802.3), as used by zip and PNG
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (!(crc & 1)).wrapping_add(1);
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Builds the payload of a save state
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { buf: Vec::new() }
+    }
+
+    pub fn u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn bool(&mut self, val: bool) {
+        self.buf.push(val as u8);
+    }
+
+    pub fn u16(&mut self, val: u16) {
+        self.buf.push(val as u8);
+        self.buf.push((val >> 8) as u8);
+    }
+
+    pub fn u32(&mut self, val: u32) {
+        self.u16(val as u16);
+        self.u16((val >> 16) as u16);
+    }
+
+    pub fn bytes(&mut self, val: &[u8]) {
+        self.buf.extend_from_slice(val);
+    }
+
+    /// Wrap the payload in a header for the given machine
+    pub fn finish(self, machine: u8) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + self.buf.len());
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION as u8);
+        out.push((VERSION >> 8) as u8);
+        out.push(machine);
+        out.push(0);
+
+        let mut header = StateWriter::new();
+        header.u32(self.buf.len() as u32);
+        header.u32(crc32(&self.buf));
+        out.extend_from_slice(&header.buf);
+        out.extend_from_slice(&self.buf);
+        out
+    }
+}
+
+/// Reads back the payload of a save state, validating the header first
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8], machine: u8) -> Result<StateReader<'a>, StateError> {
+        if data.len() < HEADER_LEN {
+            return Err(StateError::Truncated);
+        }
+        if &data[..4] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let mut header = StateReader { buf: &data[4..HEADER_LEN] };
+        let version = header.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if header.u8()? != machine {
+            return Err(StateError::MachineMismatch);
+        }
+        header.u8()?;
+
+        let len = header.u32()? as usize;
+        let checksum = header.u32()?;
+        let payload = &data[HEADER_LEN..];
+        if payload.len() != len {
+            return Err(StateError::Truncated);
+        }
+        if crc32(payload) != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        Ok(StateReader { buf: payload })
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupt),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(bytes[0] as u16 | (bytes[1] as u16) << 8)
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        let lo = self.u16()? as u32;
+        let hi = self.u16()? as u32;
+        Ok(lo | hi << 16)
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.buf.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    /// Fail unless the whole payload has been consumed
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.buf.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::Corrupt)
+        }
+    }
+}
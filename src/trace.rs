@@ -0,0 +1,102 @@
+use chip8::{Core, Registers, Tracer};
+use disasm::{self, Instruction};
+use log::{self, Level, LevelFilter, Log, Metadata, Record};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Writes diagnostics from the `log` macros to stderr
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Route log output to stderr, showing messages at `level` and above.
+/// `Level::Trace` logs every instruction executed.
+pub fn init_logger(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+/// Parse a log level name as given to --log
+pub fn parse_level(text: &str) -> Option<LevelFilter> {
+    match text {
+        "off" => Some(LevelFilter::Off),
+        _ => text.parse::<Level>().ok().map(|level| level.to_level_filter()),
+    }
+}
+
+/// Writes one line per instruction executed, in a fixed layout meant for
+/// diffing against traces from other emulators:
+///
+/// ```text
+/// <cycle> <pc> <opcode> <mnemonic> ; <changed registers>
+/// ```
+///
+/// Only registers whose value changed are listed after the `;`, and PC is
+/// only listed when the instruction didn't fall through to the next one.
+pub struct TraceFile {
+    out: BufWriter<File>,
+}
+
+impl TraceFile {
+    pub fn create(path: &str) -> io::Result<TraceFile> {
+        Ok(TraceFile { out: BufWriter::new(File::create(path)?) })
+    }
+
+    fn write(&mut self, core: &Core, before: &Registers, opcode: u16) -> io::Result<()> {
+        let after = core.registers();
+        // Decoded from the opcode actually run in case it has since been
+        // overwritten, only F000 needs to look at the following word
+        let (inst, len) = match disasm::decode(opcode) {
+            Instruction::LdILong(_) => disasm::decode_at(core.memory(), before.pc as usize)
+                .unwrap_or((Instruction::LdILong(0), 4)),
+            inst => (inst, 2),
+        };
+
+        write!(self.out, "{:10} {:04x} {:04x} {:<20} ;",
+               core.cycles(), before.pc, opcode, inst.to_string())?;
+        for (x, (old, new)) in before.v.iter().zip(after.v.iter()).enumerate() {
+            if old != new {
+                write!(self.out, " V{:X}={:02x}", x, new)?;
+            }
+        }
+        if after.i != before.i {
+            write!(self.out, " I={:04x}", after.i)?;
+        }
+        if after.pc != before.pc.wrapping_add(len as u16) {
+            write!(self.out, " PC={:04x}", after.pc)?;
+        }
+        if after.sp != before.sp {
+            write!(self.out, " SP={:x}", after.sp)?;
+        }
+        if after.dt != before.dt {
+            write!(self.out, " DT={:02x}", after.dt)?;
+        }
+        if after.st != before.st {
+            write!(self.out, " ST={:02x}", after.st)?;
+        }
+        writeln!(self.out)
+    }
+}
+
+impl Tracer for TraceFile {
+    fn instruction(&mut self, core: &Core, before: &Registers, opcode: u16) {
+        if let Err(e) = self.write(core, before, opcode) {
+            error!("Failed to write trace: {}", e);
+        }
+    }
+}
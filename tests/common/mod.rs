@@ -0,0 +1,271 @@
+//! A deliberately simple model of the CHIP-8 instruction set written from
+//! the spec, used as the reference the core is checked against.
+
+// Each test only uses part of the model
+#![allow(dead_code)]
+
+use chip8r::chip8::Core;
+#[cfg(feature = "std")]
+use chip8r::headless::KeyEvent;
+use chip8r::quirks::{IndexIncrement, Quirks};
+
+pub const MEMORY: usize = 0x1000;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    StackOverflow,
+    StackUnderflow,
+    OutOfBounds(usize),
+    InvalidKey(u8),
+}
+
+// An FX0A in progress, waiting for a key to go down and back up
+#[derive(Debug, Clone, Copy)]
+struct KeyWait {
+    reg: usize,
+    pressed: Option<usize>,
+}
+
+/// The reference: plain CHIP-8 with no extensions, written for clarity
+/// rather than speed. CXNN always gives 0 to keep runs deterministic.
+#[derive(Clone)]
+pub struct Model {
+    pub quirks: Quirks,
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub dt: u8,
+    pub st: u8,
+    pub keys: [bool; 16],
+    pub mem: Vec<u8>,
+    pub screen: Vec<u8>,
+    key_wait: Option<KeyWait>,
+}
+
+impl Model {
+    /// Starts from the same registers and memory as the core, which holds
+    /// its font and any rom loaded so far
+    pub fn new(quirks: Quirks, core: &Core) -> Model {
+        let regs = core.registers();
+        Model {
+            quirks,
+            v: regs.v,
+            i: regs.i,
+            pc: regs.pc,
+            stack: Vec::new(),
+            dt: regs.dt,
+            st: regs.st,
+            keys: [false; 16],
+            mem: core.memory().to_vec(),
+            screen: vec![0; WIDTH * HEIGHT],
+            key_wait: None,
+        }
+    }
+
+    fn load(&self, addr: usize) -> Result<u8, Fault> {
+        self.mem.get(addr).cloned().ok_or(Fault::OutOfBounds(addr))
+    }
+
+    fn store(&mut self, addr: usize, val: u8) -> Result<(), Fault> {
+        if addr >= MEMORY {
+            return Err(Fault::OutOfBounds(addr));
+        }
+        self.mem[addr] = val;
+        Ok(())
+    }
+
+    pub fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+
+    pub fn set_key(&mut self, key: usize, down: bool) {
+        self.keys[key] = down;
+
+        // FX0A takes the first key pressed once it started, when it's let go
+        match self.key_wait {
+            Some(KeyWait { pressed: None, .. }) if down => {
+                self.key_wait.as_mut().unwrap().pressed = Some(key);
+            },
+            Some(KeyWait { reg, pressed: Some(pressed) }) if !down && pressed == key => {
+                self.v[reg] = key as u8;
+                self.key_wait = None;
+            },
+            _ => {},
+        }
+    }
+
+    /// Fetch and execute the instruction at PC, unless waiting on FX0A
+    pub fn tick(&mut self) -> Result<(), Fault> {
+        if self.key_wait.is_some() {
+            return Ok(());
+        }
+        let pc = self.pc as usize;
+        let op = (self.load(pc)? as u16) << 8 | self.load(pc + 1)? as u16;
+        self.execute(op)
+    }
+
+    /// Run whole 60 Hz frames of `cycles_per_frame` instructions, applying
+    /// key events at the start of their frame and ticking the timers at
+    /// the end
+    #[cfg(feature = "std")]
+    pub fn run_frames(&mut self, frames: u64, keys: &[KeyEvent],
+                      cycles_per_frame: u32) -> Result<(), Fault> {
+        for frame in 0..frames {
+            for event in keys.iter().filter(|e| e.frame == frame) {
+                self.set_key(event.key, event.pressed);
+            }
+            for _ in 0..cycles_per_frame {
+                self.tick()?;
+            }
+            self.tick_timers();
+        }
+        Ok(())
+    }
+
+    /// The display in the same text form as `headless::screen_text`
+    pub fn screen_text(&self) -> String {
+        let mut text = String::new();
+        for row in self.screen.chunks(WIDTH) {
+            text.extend(row.iter().map(|pixel| if *pixel == 0 { '.' } else { '#' }));
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Execute `op` as though it had just been fetched from PC
+    pub fn execute(&mut self, op: u16) -> Result<(), Fault> {
+        let x = (op >> 8 & 0xf) as usize;
+        let y = (op >> 4 & 0xf) as usize;
+        let n = (op & 0xf) as usize;
+        let nn = (op & 0xff) as u8;
+        let nnn = op & 0xfff;
+        let (vx, vy) = (self.v[x], self.v[y]);
+
+        self.pc = self.pc.wrapping_add(2);
+        let mut skip = false;
+
+        match op >> 12 {
+            0x0 if op == 0x00e0 => self.screen = vec![0; WIDTH * HEIGHT],
+            0x0 => self.pc = self.stack.pop().ok_or(Fault::StackUnderflow)?,
+            0x1 => self.pc = nnn,
+            0x2 => {
+                if self.stack.len() == 16 {
+                    return Err(Fault::StackOverflow);
+                }
+                self.stack.push(self.pc);
+                self.pc = nnn;
+            },
+            0x3 => skip = vx == nn,
+            0x4 => skip = vx != nn,
+            0x5 => skip = vx == vy,
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = vx.wrapping_add(nn),
+            0x8 => {
+                // The result goes in first so VF ends up as the flag
+                let (result, flag) = match n {
+                    0x0 => (vy, None),
+                    0x1 => (vx | vy, if self.quirks.vf_reset { Some(0) } else { None }),
+                    0x2 => (vx & vy, if self.quirks.vf_reset { Some(0) } else { None }),
+                    0x3 => (vx ^ vy, if self.quirks.vf_reset { Some(0) } else { None }),
+                    0x4 => (vx.wrapping_add(vy), Some((vx as u16 + vy as u16 > 0xff) as u8)),
+                    0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+                    0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+                    0x6 | 0xe => {
+                        let src = if self.quirks.shift_vy { vy } else { vx };
+                        if n == 0x6 {
+                            (src >> 1, Some(src & 1))
+                        } else {
+                            (src << 1, Some(src >> 7))
+                        }
+                    },
+                    _ => unreachable!(),
+                };
+                self.v[x] = result;
+                if let Some(flag) = flag {
+                    self.v[0xf] = flag;
+                }
+            },
+            0x9 => skip = vx != vy,
+            0xa => self.i = nnn,
+            0xb => {
+                let offset = if self.quirks.jump_vx { vx } else { self.v[0] };
+                self.pc = nnn + offset as u16;
+            },
+            0xc => self.v[x] = 0,
+            0xd => {
+                let (x0, y0) = (vx as usize % WIDTH, vy as usize % HEIGHT);
+                self.v[0xf] = 0;
+                for row in 0..n {
+                    let mut py = y0 + row;
+                    if py >= HEIGHT {
+                        if self.quirks.clip_sprites {
+                            break;
+                        }
+                        py %= HEIGHT;
+                    }
+                    let bits = self.load(self.i as usize + row)?;
+                    for col in 0..8 {
+                        let mut px = x0 + col;
+                        if bits & (0x80 >> col) == 0 {
+                            continue;
+                        }
+                        if px >= WIDTH {
+                            if self.quirks.clip_sprites {
+                                continue;
+                            }
+                            px %= WIDTH;
+                        }
+                        let pixel = &mut self.screen[py * WIDTH + px];
+                        if *pixel != 0 {
+                            self.v[0xf] = 1;
+                        }
+                        *pixel ^= 1;
+                    }
+                }
+            },
+            0xe if vx > 0xf => return Err(Fault::InvalidKey(vx)),
+            0xe if nn == 0x9e => skip = self.keys[vx as usize],
+            0xe => skip = !self.keys[vx as usize],
+            _ => match nn {
+                0x07 => self.v[x] = self.dt,
+                0x0a => self.key_wait = Some(KeyWait { reg: x, pressed: None }),
+                0x15 => self.dt = vx,
+                0x18 => self.st = vx,
+                0x1e => self.i = self.i.wrapping_add(vx as u16),
+                0x29 => self.i = (vx & 0xf) as u16 * 5,
+                0x33 => {
+                    let i = self.i as usize;
+                    self.store(i, vx / 100)?;
+                    self.store(i + 1, vx / 10 % 10)?;
+                    self.store(i + 2, vx % 10)?;
+                },
+                0x55 | 0x65 => {
+                    for r in 0..x + 1 {
+                        let addr = self.i as usize + r;
+                        if nn == 0x55 {
+                            let val = self.v[r];
+                            self.store(addr, val)?;
+                        } else {
+                            self.v[r] = self.load(addr)?;
+                        }
+                    }
+                    self.i = self.i.wrapping_add(match self.quirks.load_store_index {
+                        IndexIncrement::Unchanged => 0,
+                        IndexIncrement::ByX => x as u16,
+                        IndexIncrement::ByXPlusOne => x as u16 + 1,
+                    });
+                },
+                _ => unreachable!(),
+            },
+        }
+
+        if skip {
+            self.pc = self.pc.wrapping_add(2);
+        }
+        Ok(())
+    }
+}
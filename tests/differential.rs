@@ -0,0 +1,268 @@
+//! Differential testing of the core against the reference model in
+//! tests/common, a deliberately simple CHIP-8 written from the spec.
+//!
+//! Random sequences of instructions, timer ticks and key changes are run
+//! through both under every quirk preset, comparing the whole machine
+//! after each step. A failing sequence is shrunk down to the fewest steps
+//! that still disagree before being reported along with its seed.
+
+extern crate chip8r;
+
+mod common;
+
+use chip8r::chip8::{Core, CoreError, Machine, Registers};
+use chip8r::disasm;
+use chip8r::quirks::{Preset, Quirks};
+use common::{Fault, Model, HEIGHT, MEMORY, WIDTH};
+use std::fmt;
+
+const SEQUENCES: u64 = 200;
+const STEPS: usize = 200;
+
+/// xorshift64*, so sequences only depend on their seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len() as u64) as usize]
+    }
+
+    // Registers, leaning on VF since it's where the edge cases are
+    fn reg(&mut self) -> u16 {
+        if self.below(4) == 0 { 0xf } else { self.below(16) as u16 }
+    }
+
+    // Bytes, leaning on the carry and borrow boundaries
+    fn byte(&mut self) -> u16 {
+        if self.below(2) == 0 {
+            self.pick(&[0x00, 0x01, 0x7f, 0x80, 0xfe, 0xff])
+        } else {
+            self.below(0x100) as u16
+        }
+    }
+
+    // Addresses, leaning on the end of memory
+    fn addr(&mut self) -> u16 {
+        if self.below(4) == 0 { 0xff0 + self.below(0x10) as u16 } else { self.below(0x1000) as u16 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Op(u16),
+    Timers,
+    Key(usize, bool),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Step::Op(op) => write!(f, "{:04X}  {}", op, disasm::decode(op)),
+            Step::Timers => write!(f, "(timers tick)"),
+            Step::Key(key, true) => write!(f, "(key {:X} down)", key),
+            Step::Key(key, false) => write!(f, "(key {:X} up)", key),
+        }
+    }
+}
+
+// Everything in the base instruction set apart from FX0A, which would
+// stall the sequence, with CXNN masked to 0 to keep it deterministic
+fn random_op(rng: &mut Rng) -> u16 {
+    let (x, y) = (rng.reg() << 8, rng.reg() << 4);
+    match rng.below(27) {
+        0 => 0x00e0,
+        1 => 0x00ee,
+        2 => 0x1000 | rng.addr(),
+        3 => 0x2000 | rng.addr(),
+        4 => 0x3000 | x | rng.byte(),
+        5 => 0x4000 | x | rng.byte(),
+        6 => 0x5000 | x | y,
+        7 => 0x6000 | x | rng.byte(),
+        8 => 0x7000 | x | rng.byte(),
+        9..=11 => 0x8000 | x | y | rng.pick(&[0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xe]),
+        12 => 0x9000 | x | y,
+        13 | 14 => 0xa000 | rng.addr(),
+        15 => 0xb000 | rng.addr(),
+        16 => 0xc000 | x,
+        17 | 18 => 0xd000 | x | y | (1 + rng.below(15) as u16),
+        19 => 0xe09e | x,
+        20 => 0xe0a1 | x,
+        _ => 0xf000 | x | rng.pick(&[0x07, 0x15, 0x18, 0x1e, 0x29, 0x33, 0x55, 0x65]),
+    }
+}
+
+fn random_step(rng: &mut Rng) -> Step {
+    match rng.below(20) {
+        0 => Step::Timers,
+        1 => Step::Key(rng.below(16) as usize, rng.below(2) == 0),
+        _ => Step::Op(random_op(rng)),
+    }
+}
+
+// Apply a step to the model the way `run` applies it to the core
+fn model_step(model: &mut Model, step: Step) -> Result<(), Fault> {
+    match step {
+        Step::Op(op) => model.execute(op),
+        Step::Timers => {
+            model.tick_timers();
+            Ok(())
+        },
+        Step::Key(key, down) => {
+            model.set_key(key, down);
+            Ok(())
+        },
+    }
+}
+
+fn same_fault(fault: Fault, err: CoreError) -> bool {
+    match (fault, err) {
+        (Fault::StackOverflow, CoreError::StackOverflow { .. }) => true,
+        (Fault::StackUnderflow, CoreError::StackUnderflow { .. }) => true,
+        (Fault::OutOfBounds(a), CoreError::MemoryOutOfBounds { addr, .. }) => a == addr,
+        (Fault::InvalidKey(a), CoreError::InvalidKey { key, .. }) => a == key,
+        _ => false,
+    }
+}
+
+// Where the core and the model disagree, if they do
+fn compare(core: &Core, model: &Model) -> Option<String> {
+    let regs = core.registers();
+    let expected = Registers {
+        v: model.v,
+        i: model.i,
+        pc: model.pc,
+        sp: model.stack.len() as u8,
+        dt: model.dt,
+        st: model.st,
+    };
+    if regs != expected {
+        return Some(format!("registers differ\n  core:  {:?}\n  model: {:?}", regs, expected));
+    }
+    if core.stack() != &model.stack[..] {
+        return Some(format!("stack differs\n  core:  {:?}\n  model: {:?}",
+                            core.stack(), model.stack));
+    }
+    if let Some(addr) = (0..MEMORY).find(|a| core.memory()[*a] != model.mem[*a]) {
+        return Some(format!("memory differs at 0x{:03x}: core {:02x}, model {:02x}",
+                            addr, core.memory()[addr], model.mem[addr]));
+    }
+    if let Some(idx) = (0..WIDTH * HEIGHT).find(|p| core.frame_buffer()[*p] != model.screen[*p]) {
+        return Some(format!("pixel ({}, {}) differs: core {}, model {}", idx % WIDTH,
+                            idx / WIDTH, core.frame_buffer()[idx], model.screen[idx]));
+    }
+    None
+}
+
+// Run a sequence through both from the same starting registers. Returns
+// the index of the first step they disagree on and how.
+fn run(quirks: Quirks, start: &Registers, steps: &[Step]) -> Option<(usize, String)> {
+    let mut core = Core::new(Machine::Chip8, quirks);
+    core.set_registers(start);
+    let mut model = Model::new(quirks, &core);
+
+    for (idx, step) in steps.iter().enumerate() {
+        let expected = model_step(&mut model, *step);
+        let result = match *step {
+            Step::Op(op) => core.execute_opcode(op),
+            Step::Timers => {
+                core.tick_timers();
+                Ok(())
+            },
+            Step::Key(key, true) => {
+                core.set_key(key);
+                Ok(())
+            },
+            Step::Key(key, false) => {
+                core.clear_key(key);
+                Ok(())
+            },
+        };
+
+        // The state after a fault isn't specified, only that it happens
+        match (expected, result) {
+            (Ok(()), Ok(())) => {},
+            (Err(fault), Err(err)) if same_fault(fault, err) => return None,
+            (expected, result) => return Some((idx, format!("expected {:?}, got {:?}",
+                                                            expected, result))),
+        }
+        if let Some(diff) = compare(&core, &model) {
+            return Some((idx, diff));
+        }
+    }
+    None
+}
+
+// Drop as many steps as possible while the sequence still fails, trying
+// large chunks first
+fn shrink(quirks: Quirks, start: &Registers, mut steps: Vec<Step>) -> Vec<Step> {
+    let mut chunk = steps.len() / 2;
+    while chunk > 0 {
+        let mut pos = 0;
+        while pos < steps.len() {
+            let mut candidate = steps.clone();
+            candidate.drain(pos..(pos + chunk).min(steps.len()));
+            if run(quirks, start, &candidate).is_some() {
+                steps = candidate;
+            } else {
+                pos += chunk;
+            }
+        }
+        chunk /= 2;
+    }
+    steps
+}
+
+fn check_preset(preset: Preset) {
+    let quirks = Quirks::from_preset(preset);
+
+    for seed in 0..SEQUENCES {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15 ^ (seed + 1).wrapping_mul(0xff51_afd7_ed55_8ccd));
+        let mut v = [0u8; 16];
+        for reg in v.iter_mut() {
+            *reg = rng.byte() as u8;
+        }
+        let start = Registers { v, i: rng.addr(), pc: 0x200, sp: 0, dt: 0, st: 0 };
+        let steps: Vec<Step> = (0..STEPS).map(|_| random_step(&mut rng)).collect();
+
+        // Ends at the first step that disagrees, everything after it is
+        // noise as far as shrinking goes
+        if let Some((idx, _)) = run(quirks, &start, &steps) {
+            let steps = shrink(quirks, &start, steps[..idx + 1].to_vec());
+            let (_, diff) = run(quirks, &start, &steps).unwrap();
+            let listing: Vec<String> = steps.iter().map(|s| format!("  {}", s)).collect();
+            panic!("{} quirks, seed {}: core disagrees with the model after\n{}\n\
+                    starting from {:?}\n{}", preset, seed, listing.join("\n"), start, diff);
+        }
+    }
+}
+
+#[test]
+fn matches_model_with_vip_quirks() {
+    check_preset(Preset::CosmacVip);
+}
+
+#[test]
+fn matches_model_with_chip48_quirks() {
+    check_preset(Preset::Chip48);
+}
+
+#[test]
+fn matches_model_with_schip_quirks() {
+    check_preset(Preset::SuperChip11);
+}
+
+#[test]
+fn matches_model_with_octo_quirks() {
+    check_preset(Preset::Octo);
+}
@@ -0,0 +1,117 @@
+//! Compiles the Octo programs in tests/octo and compares the result with
+//! the .ch8 rom next to each one, then checks where compile errors are
+//! reported.
+//!
+//! The expected roms follow the instruction sequences the Octo compiler
+//! emits for each statement, laid out one statement per group of bytes so
+//! a mismatch can be traced back to the source. They were assembled by
+//! hand, not by running Octo, so regenerate them with Octo if they are
+//! ever in doubt.
+
+extern crate chip8r;
+
+use chip8r::octo;
+use std::fs;
+use std::path::PathBuf;
+
+fn fixture(name: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "octo", name].iter().collect();
+    let source = fs::read_to_string(path.with_extension("8o")).expect("unable to read source");
+    let expected = fs::read(path.with_extension("ch8")).expect("unable to read rom");
+
+    let rom = octo::compile(&source).unwrap_or_else(|e| panic!("{}.8o failed to compile: {}", name, e));
+    if rom != expected {
+        let hex = |bytes: &[u8]| bytes.iter().map(|b| format!("{:02X}", b)).collect::<Vec<_>>().join(" ");
+        panic!("{}.8o compiled to\n{}\nexpected\n{}", name, hex(&rom), hex(&expected));
+    }
+}
+
+fn error(source: &str) -> (usize, usize, String) {
+    let e = octo::compile(source).unwrap_err();
+    (e.line, e.column, e.message)
+}
+
+#[test]
+fn assignments() {
+    fixture("assign");
+}
+
+#[test]
+fn control_flow() {
+    fixture("control");
+}
+
+#[test]
+fn directives() {
+    fixture("directives");
+}
+
+#[test]
+fn subroutines() {
+    fixture("subroutines");
+}
+
+#[test]
+fn comparisons() {
+    fixture("compare");
+}
+
+#[test]
+fn undefined_names() {
+    assert_eq!(error(": main\n\tjump nowhere\n"), (2, 7, "undefined name 'nowhere'".to_string()));
+    assert_eq!(error(": main\n\tv0 := SPEED\n"), (2, 8, "undefined name 'SPEED'".to_string()));
+    assert_eq!(error(": main\n\tv0 := 1\n:calc X { later }\n: later\n"),
+               (3, 11, "undefined name 'later'".to_string()));
+}
+
+#[test]
+fn missing_main() {
+    assert_eq!(error("v0 := 1\n"), (1, 7, "program is missing a 'main' label".to_string()));
+}
+
+#[test]
+fn unbalanced_blocks() {
+    assert_eq!(error(": main\n\tagain\n"), (2, 2, "'again' without a matching 'loop'".to_string()));
+    assert_eq!(error(": main\n\tloop\n\tv0 := 1\n"),
+               (2, 2, "'loop' without a matching 'again'".to_string()));
+    assert_eq!(error(": main\n\telse\n"), (2, 2, "'else' without a matching 'begin'".to_string()));
+    assert_eq!(error(": main\n\tif v0 == 1 begin\n"),
+               (2, 2, "'begin' without a matching 'end'".to_string()));
+    assert_eq!(error(": main\n\twhile v0 == 1\n"), (2, 2, "'while' outside of a loop".to_string()));
+}
+
+#[test]
+fn bad_operands() {
+    assert_eq!(error(": main\n\tv0 := 256\n"), (2, 8, "byte 256 out of range (-128..255)".to_string()));
+    assert_eq!(error(": main\n\tv0 |= 1\n"), (2, 8, "expected a register, got '1'".to_string()));
+    assert_eq!(error(": main\n\tif v0 then clear\n"), (2, 8, "'then' is not a comparison".to_string()));
+    assert_eq!(error(": main\n\tif v0 == 1 clear\n"),
+               (2, 13, "expected 'then' or 'begin', got 'clear'".to_string()));
+    assert_eq!(error(": main\n\tsprite v0 v1 16\n"), (2, 15, "value 16 out of range (0..15)".to_string()));
+}
+
+#[test]
+fn bad_definitions() {
+    assert_eq!(error(": a\n: a\n"), (2, 3, "'a' is already defined".to_string()));
+    assert_eq!(error(":const v0 1\n"), (1, 8, "'v0' cannot be used as a constant name".to_string()));
+    assert_eq!(error(":macro loop {\n}\n"), (1, 8, "'loop' cannot be used as a macro name".to_string()));
+    assert_eq!(error(":const X\n"), (1, 8, "unexpected end of file".to_string()));
+    assert_eq!(error(":frobnicate\n"), (1, 1, "unknown directive ':frobnicate'".to_string()));
+}
+
+#[test]
+fn overlapping_data() {
+    assert_eq!(error(": main\n\t1 2\n:org 0x201\n\t3\n"),
+               (4, 2, "data overlap at address 0x0201".to_string()));
+    assert_eq!(error(":org 0x10000\n"), (1, 6, "address 65536 out of range (512..65535)".to_string()));
+}
+
+#[test]
+fn jumps_out_of_reach() {
+    assert_eq!(error(": main\n\tif v0 == 1 begin\n:org 0x1234\n\tend\n"),
+               (4, 2, "'end' jumps to 0x1234, out of reach of a 12 bit address".to_string()));
+    assert_eq!(error(": main\n\tif v0 == 1 begin\n:org 0x1234\n\telse\n"),
+               (4, 2, "'else' jumps to 0x1236, out of reach of a 12 bit address".to_string()));
+    assert_eq!(error(": main\n\tloop\n\twhile v0 == 1\n:org 0x1234\n\tagain\n"),
+               (5, 2, "'again' jumps to 0x1236, out of reach of a 12 bit address".to_string()));
+}
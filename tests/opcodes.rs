@@ -0,0 +1,755 @@
+//! One or more tests per instruction, each setting up a core, running a
+//! single opcode with `Core::execute_opcode` and checking what changed.
+
+extern crate chip8r;
+
+use chip8r::chip8::{Core, CoreError, LoadError, Machine, MemorySize, Random, Registers, XorShift};
+use chip8r::quirks::{IndexIncrement, Quirks};
+
+fn core() -> Core {
+    Core::new(Machine::Chip8, Quirks::cosmac_vip())
+}
+
+fn core_with(machine: Machine, quirks: Quirks) -> Core {
+    Core::new(machine, quirks)
+}
+
+fn set_v(core: &mut Core, values: &[(usize, u8)]) {
+    let mut regs = core.registers();
+    for &(x, val) in values {
+        regs.v[x] = val;
+    }
+    core.set_registers(&regs);
+}
+
+fn set_i(core: &mut Core, i: u16) {
+    core.set_registers(&Registers { i, ..core.registers() });
+}
+
+fn run(core: &mut Core, opcode: u16) {
+    core.execute_opcode(opcode)
+        .unwrap_or_else(|e| panic!("0x{:04x} faulted: {}", opcode, e));
+}
+
+fn v(core: &Core, x: usize) -> u8 {
+    core.registers().v[x]
+}
+
+fn pc(core: &Core) -> u16 {
+    core.registers().pc
+}
+
+fn pixel(core: &Core, x: usize, y: usize) -> u8 {
+    let (width, _) = core.resolution();
+    core.frame_buffer()[y * width + x]
+}
+
+fn lit(core: &Core) -> usize {
+    core.frame_buffer().iter().filter(|p| **p != 0).count()
+}
+
+// Draw an 8x1 row of pixels at (x, y)
+fn draw_row(core: &mut Core, x: u8, y: u8, bits: u8) {
+    core.write_memory(0x300, &[bits]);
+    set_i(core, 0x300);
+    set_v(core, &[(0, x), (1, y)]);
+    run(core, 0xd011);
+}
+
+#[test]
+fn cls_clears_every_pixel() {
+    let mut core = core();
+    draw_row(&mut core, 0, 0, 0xff);
+    draw_row(&mut core, 56, 31, 0xff);
+    assert_eq!(pixel(&core, 63, 31), 1);
+
+    run(&mut core, 0x00e0);
+    assert_eq!(lit(&core), 0);
+    assert_eq!(pixel(&core, 63, 31), 0);
+}
+
+#[test]
+fn cls_only_clears_selected_planes() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    run(&mut core, 0xf301);
+    core.write_memory(0x300, &[0x80, 0x80]);
+    set_i(&mut core, 0x300);
+    run(&mut core, 0xd011);
+    assert_eq!(pixel(&core, 0, 0), 3);
+
+    run(&mut core, 0xf101);
+    run(&mut core, 0x00e0);
+    assert_eq!(pixel(&core, 0, 0), 2);
+}
+
+#[test]
+fn call_and_ret() {
+    let mut core = core();
+    run(&mut core, 0x2400);
+    assert_eq!(pc(&core), 0x400);
+    assert_eq!(core.stack(), &[0x202]);
+
+    run(&mut core, 0x00ee);
+    assert_eq!(pc(&core), 0x202);
+    assert!(core.stack().is_empty());
+}
+
+#[test]
+fn ret_with_empty_stack_underflows() {
+    let mut core = core();
+    assert_eq!(core.execute_opcode(0x00ee), Err(CoreError::StackUnderflow { pc: 0x200 }));
+}
+
+#[test]
+fn call_past_sixteen_levels_overflows() {
+    let mut core = core();
+    for _ in 0..16 {
+        run(&mut core, 0x2200);
+    }
+    assert_eq!(core.stack().len(), 16);
+    assert_eq!(core.execute_opcode(0x2200), Err(CoreError::StackOverflow { pc: 0x200 }));
+}
+
+#[test]
+fn jp() {
+    let mut core = core();
+    run(&mut core, 0x1abc);
+    assert_eq!(pc(&core), 0xabc);
+}
+
+#[test]
+fn se_byte() {
+    let mut core = core();
+    set_v(&mut core, &[(3, 0x42)]);
+    run(&mut core, 0x3342);
+    assert_eq!(pc(&core), 0x204);
+    run(&mut core, 0x3343);
+    assert_eq!(pc(&core), 0x206);
+}
+
+#[test]
+fn sne_byte() {
+    let mut core = core();
+    set_v(&mut core, &[(3, 0x42)]);
+    run(&mut core, 0x4342);
+    assert_eq!(pc(&core), 0x202);
+    run(&mut core, 0x4300);
+    assert_eq!(pc(&core), 0x206);
+}
+
+#[test]
+fn se_reg() {
+    let mut core = core();
+    set_v(&mut core, &[(1, 7), (2, 7), (3, 8)]);
+    run(&mut core, 0x5120);
+    assert_eq!(pc(&core), 0x204);
+    run(&mut core, 0x5130);
+    assert_eq!(pc(&core), 0x206);
+}
+
+#[test]
+fn sne_reg() {
+    let mut core = core();
+    set_v(&mut core, &[(1, 7), (2, 7), (3, 8)]);
+    run(&mut core, 0x9120);
+    assert_eq!(pc(&core), 0x202);
+    run(&mut core, 0x9130);
+    assert_eq!(pc(&core), 0x206);
+}
+
+#[test]
+fn skips_step_over_long_i_on_xochip() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    core.write_memory(0x202, &[0xf0, 0x00, 0x12, 0x34]);
+    run(&mut core, 0x3000);
+    assert_eq!(pc(&core), 0x206);
+}
+
+#[test]
+fn ld_byte() {
+    let mut core = core();
+    run(&mut core, 0x6a5c);
+    assert_eq!(v(&core, 0xa), 0x5c);
+}
+
+#[test]
+fn add_byte_wraps_without_touching_vf() {
+    let mut core = core();
+    set_v(&mut core, &[(1, 0xff), (0xf, 7)]);
+    run(&mut core, 0x7102);
+    assert_eq!(v(&core, 1), 0x01);
+    assert_eq!(v(&core, 0xf), 7);
+}
+
+#[test]
+fn ld_reg() {
+    let mut core = core();
+    set_v(&mut core, &[(2, 0x99)]);
+    run(&mut core, 0x8120);
+    assert_eq!(v(&core, 1), 0x99);
+}
+
+#[test]
+fn logic_ops() {
+    for &(op, result) in &[(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+        let mut core = core_with(Machine::Chip8, Quirks::octo());
+        set_v(&mut core, &[(0, 0b1100), (1, 0b1010), (0xf, 5)]);
+        run(&mut core, op);
+        assert_eq!(v(&core, 0), result, "0x{:04x}", op);
+        assert_eq!(v(&core, 0xf), 5, "0x{:04x} touched VF", op);
+    }
+}
+
+#[test]
+fn logic_ops_reset_vf_on_vip() {
+    for op in &[0x8011, 0x8012, 0x8013] {
+        let mut core = core();
+        set_v(&mut core, &[(0xf, 5)]);
+        run(&mut core, *op);
+        assert_eq!(v(&core, 0xf), 0, "0x{:04x}", op);
+    }
+}
+
+#[test]
+fn add_reg_carry() {
+    let mut core = core();
+    set_v(&mut core, &[(0, 0xff), (1, 0x00)]);
+    run(&mut core, 0x8014);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0xff, 0));
+
+    set_v(&mut core, &[(0, 0xff), (1, 0x01)]);
+    run(&mut core, 0x8014);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0x00, 1));
+}
+
+#[test]
+fn add_reg_with_vf_operands() {
+    // The flag wins when VF is the destination
+    let mut core = core();
+    set_v(&mut core, &[(0xf, 0x10), (1, 0x20)]);
+    run(&mut core, 0x8f14);
+    assert_eq!(v(&core, 0xf), 0);
+
+    // VF as the source is read before the flag is written
+    set_v(&mut core, &[(0, 0xf0), (0xf, 0x20)]);
+    run(&mut core, 0x80f4);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0x10, 1));
+}
+
+#[test]
+fn sub_borrow() {
+    let mut core = core();
+    set_v(&mut core, &[(0, 5), (1, 5)]);
+    run(&mut core, 0x8015);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0, 1));
+
+    set_v(&mut core, &[(0, 5), (1, 6)]);
+    run(&mut core, 0x8015);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0xff, 0));
+}
+
+#[test]
+fn sub_into_vf() {
+    let mut core = core();
+    set_v(&mut core, &[(0xf, 3), (1, 1)]);
+    run(&mut core, 0x8f15);
+    assert_eq!(v(&core, 0xf), 1);
+}
+
+#[test]
+fn subn_borrow() {
+    let mut core = core();
+    set_v(&mut core, &[(0, 5), (1, 8)]);
+    run(&mut core, 0x8017);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (3, 1));
+
+    set_v(&mut core, &[(0, 8), (1, 5)]);
+    run(&mut core, 0x8017);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0xfd, 0));
+}
+
+#[test]
+fn subn_into_vf() {
+    let mut core = core();
+    set_v(&mut core, &[(0xf, 3), (1, 1)]);
+    run(&mut core, 0x8f17);
+    assert_eq!(v(&core, 0xf), 0);
+}
+
+#[test]
+fn shifts_vy_on_vip() {
+    let mut core = core();
+    set_v(&mut core, &[(0, 0), (1, 0x81)]);
+    run(&mut core, 0x8016);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0x40, 1));
+
+    set_v(&mut core, &[(0, 0), (1, 0x81)]);
+    run(&mut core, 0x801e);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0x02, 1));
+}
+
+#[test]
+fn shifts_vx_on_schip() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    set_v(&mut core, &[(0, 0x02), (1, 0xff)]);
+    run(&mut core, 0x8016);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0x01, 0));
+
+    set_v(&mut core, &[(0, 0x40), (1, 0xff)]);
+    run(&mut core, 0x801e);
+    assert_eq!((v(&core, 0), v(&core, 0xf)), (0x80, 0));
+}
+
+#[test]
+fn shift_into_vf() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    set_v(&mut core, &[(0xf, 0x02)]);
+    run(&mut core, 0x8f06);
+    assert_eq!(v(&core, 0xf), 0);
+
+    set_v(&mut core, &[(0xf, 0x80)]);
+    run(&mut core, 0x8f0e);
+    assert_eq!(v(&core, 0xf), 1);
+}
+
+#[test]
+fn ld_i() {
+    let mut core = core();
+    run(&mut core, 0xa123);
+    assert_eq!(core.registers().i, 0x123);
+}
+
+#[test]
+fn jp_offset_uses_v0() {
+    let mut core = core();
+    set_v(&mut core, &[(0, 4), (3, 8)]);
+    run(&mut core, 0xb300);
+    assert_eq!(pc(&core), 0x304);
+}
+
+#[test]
+fn jp_offset_uses_vx_on_schip() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    set_v(&mut core, &[(0, 4), (3, 8)]);
+    run(&mut core, 0xb300);
+    assert_eq!(pc(&core), 0x308);
+}
+
+#[test]
+fn rnd_is_masked() {
+    let mut core = core();
+    for _ in 0..100 {
+        run(&mut core, 0xc500);
+        assert_eq!(v(&core, 5), 0);
+        run(&mut core, 0xc50f);
+        assert_eq!(v(&core, 5) & 0xf0, 0);
+    }
+}
+
+struct Fixed(u8);
+
+impl Random for Fixed {
+    fn random_byte(&mut self) -> u8 {
+        self.0
+    }
+}
+
+#[test]
+fn rnd_uses_random_source() {
+    let mut core = core();
+    core.set_random(Box::new(Fixed(0xab)));
+    run(&mut core, 0xc5f0);
+    assert_eq!(v(&core, 5), 0xa0);
+
+    // The same seed gives the same numbers
+    let mut other = self::core();
+    core.set_random(Box::new(XorShift::new(1)));
+    other.set_random(Box::new(XorShift::new(1)));
+    for _ in 0..10 {
+        run(&mut core, 0xc5ff);
+        run(&mut other, 0xc5ff);
+        assert_eq!(v(&core, 5), v(&other, 5));
+    }
+}
+
+#[test]
+fn drw_sets_pixels_and_reports_collisions() {
+    let mut core = core();
+    draw_row(&mut core, 10, 5, 0b1010_0000);
+    assert_eq!((pixel(&core, 10, 5), pixel(&core, 11, 5), pixel(&core, 12, 5)), (1, 0, 1));
+    assert_eq!(v(&core, 0xf), 0);
+
+    draw_row(&mut core, 10, 5, 0b1000_0000);
+    assert_eq!(pixel(&core, 10, 5), 0);
+    assert_eq!(v(&core, 0xf), 1);
+}
+
+#[test]
+fn drw_wraps_start_position() {
+    let mut core = core();
+    draw_row(&mut core, 64 + 3, 32 + 2, 0x80);
+    assert_eq!(pixel(&core, 3, 2), 1);
+}
+
+#[test]
+fn drw_clips_at_right_edge_on_vip() {
+    for x in 60..64 {
+        let mut core = core();
+        draw_row(&mut core, x, 0, 0xff);
+        assert_eq!(lit(&core), 64 - x as usize, "x = {}", x);
+        assert_eq!(pixel(&core, 63, 0), 1);
+        assert_eq!(pixel(&core, 0, 0), 0);
+    }
+}
+
+#[test]
+fn drw_wraps_at_right_edge_on_octo() {
+    for x in 60..64 {
+        let mut core = core_with(Machine::Chip8, Quirks::octo());
+        draw_row(&mut core, x, 0, 0xff);
+        assert_eq!(lit(&core), 8, "x = {}", x);
+        assert_eq!(pixel(&core, 63, 0), 1);
+        assert_eq!(pixel(&core, 0, 0), 1);
+        assert_eq!(pixel(&core, (x as usize + 7) % 64, 0), 1);
+    }
+}
+
+#[test]
+fn drw_clips_at_bottom_edge() {
+    let mut core = core();
+    core.write_memory(0x300, &[0x80; 4]);
+    set_i(&mut core, 0x300);
+    set_v(&mut core, &[(0, 0), (1, 30)]);
+    run(&mut core, 0xd014);
+    assert_eq!(lit(&core), 2);
+    assert_eq!(pixel(&core, 0, 0), 0);
+}
+
+#[test]
+fn drw_16x16_on_schip() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    run(&mut core, 0x00ff);
+    core.write_memory(0x300, &[0xff; 32]);
+    set_i(&mut core, 0x300);
+    set_v(&mut core, &[(0, 0), (1, 0)]);
+    run(&mut core, 0xd010);
+    assert_eq!(lit(&core), 256);
+    assert_eq!(pixel(&core, 15, 15), 1);
+    assert_eq!(pixel(&core, 16, 0), 0);
+}
+
+#[test]
+fn drw_past_end_of_memory_faults() {
+    let mut core = core();
+    set_i(&mut core, 0xffe);
+    assert_eq!(core.execute_opcode(0xd005),
+               Err(CoreError::MemoryOutOfBounds { pc: 0x200, addr: 0x1000 }));
+}
+
+#[test]
+fn skp_and_sknp() {
+    let mut core = core();
+    set_v(&mut core, &[(2, 0xa)]);
+    run(&mut core, 0xe29e);
+    assert_eq!(pc(&core), 0x202);
+    run(&mut core, 0xe2a1);
+    assert_eq!(pc(&core), 0x206);
+
+    core.set_key(0xa);
+    run(&mut core, 0xe29e);
+    assert_eq!(pc(&core), 0x20a);
+    run(&mut core, 0xe2a1);
+    assert_eq!(pc(&core), 0x20c);
+}
+
+#[test]
+fn skp_key_out_of_range() {
+    let mut core = core();
+    set_v(&mut core, &[(2, 0x10)]);
+    assert_eq!(core.execute_opcode(0xe29e), Err(CoreError::InvalidKey { pc: 0x200, key: 0x10 }));
+    assert_eq!(core.execute_opcode(0xe2a1), Err(CoreError::InvalidKey { pc: 0x202, key: 0x10 }));
+}
+
+#[test]
+fn load_rom_too_large() {
+    let mut core = core();
+    assert_eq!(core.load_rom(&[0; 0xe00]), Ok(()));
+    assert_eq!(core.load_rom(&[0; 0xe01]), Err(LoadError::TooLarge { size: 0xe01, max: 0xe00 }));
+}
+
+#[test]
+fn load_rom_at_address() {
+    let mut core = core();
+    core.load_rom_at(&[0x60, 0x2a], 0x600).unwrap();
+    assert_eq!(pc(&core), 0x600);
+    assert_eq!(&core.memory()[0x600..0x602], &[0x60, 0x2a]);
+    core.tick().unwrap();
+    assert_eq!(v(&core, 0), 0x2a);
+
+    // A reset goes back to where the ROM was loaded
+    core.soft_reset();
+    assert_eq!(pc(&core), 0x600);
+
+    assert_eq!(core.load_rom_at(&[0; 0xa01], 0x600),
+               Err(LoadError::TooLarge { size: 0xa01, max: 0xa00 }));
+    assert_eq!(core.load_rom_at(&[0], 0x100), Err(LoadError::InvalidAddress { addr: 0x100 }));
+    assert_eq!(core.load_rom_at(&[0], 0x1000), Err(LoadError::InvalidAddress { addr: 0x1000 }));
+}
+
+#[test]
+fn memory_sizes() {
+    assert_eq!(core().memory().len(), 0x1000);
+    assert_eq!(core_with(Machine::XoChip, Quirks::octo()).memory().len(), 0x10000);
+
+    let mut core = Core::with_memory(Machine::Chip8, Quirks::cosmac_vip(), MemorySize::Extended);
+    assert_eq!(core.memory().len(), 0x10000);
+    core.load_rom_at(&[0x12, 0x00], 0x8000).unwrap();
+    assert_eq!(pc(&core), 0x8000);
+}
+
+#[test]
+fn timers() {
+    let mut core = core();
+    set_v(&mut core, &[(1, 30), (2, 40)]);
+    run(&mut core, 0xf115);
+    run(&mut core, 0xf218);
+    assert_eq!((core.registers().dt, core.registers().st), (30, 40));
+
+    core.tick_timers();
+    run(&mut core, 0xf307);
+    assert_eq!(v(&core, 3), 29);
+}
+
+#[test]
+fn ld_key_waits_for_press_and_release() {
+    let mut core = core();
+    run(&mut core, 0xf40a);
+    assert!(core.waiting_for_key());
+
+    core.set_key(7);
+    assert!(core.waiting_for_key());
+    core.clear_key(7);
+    assert!(!core.waiting_for_key());
+    assert_eq!(v(&core, 4), 7);
+}
+
+#[test]
+fn add_i() {
+    let mut core = core();
+    set_i(&mut core, 0x100);
+    set_v(&mut core, &[(1, 0x20), (0xf, 9)]);
+    run(&mut core, 0xf11e);
+    assert_eq!(core.registers().i, 0x120);
+    assert_eq!(v(&core, 0xf), 9);
+}
+
+#[test]
+fn add_i_past_fff() {
+    // I isn't masked to 12 bits and VF isn't used as an overflow flag
+    let mut core = core();
+    set_i(&mut core, 0xfff);
+    set_v(&mut core, &[(1, 1), (0xf, 9)]);
+    run(&mut core, 0xf11e);
+    assert_eq!(core.registers().i, 0x1000);
+    assert_eq!(v(&core, 0xf), 9);
+}
+
+#[test]
+fn ld_font() {
+    let mut core = core();
+    set_v(&mut core, &[(1, 0xa)]);
+    run(&mut core, 0xf129);
+    let i = core.registers().i as usize;
+    assert_eq!(&core.memory()[i..i + 5], &[0xf0, 0x90, 0xf0, 0x90, 0x90]);
+}
+
+#[test]
+fn ld_big_font_on_schip() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    set_v(&mut core, &[(1, 0x1)]);
+    run(&mut core, 0xf130);
+    let i = core.registers().i as usize;
+    assert_eq!(&core.memory()[i..i + 10],
+               &[0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xff, 0xff]);
+}
+
+#[test]
+fn bcd() {
+    for &(val, digits) in &[(255, [2, 5, 5]), (0, [0, 0, 0]), (9, [0, 0, 9]), (100, [1, 0, 0])] {
+        let mut core = core();
+        set_i(&mut core, 0x300);
+        set_v(&mut core, &[(6, val)]);
+        run(&mut core, 0xf633);
+        assert_eq!(&core.memory()[0x300..0x303], &digits, "BCD of {}", val);
+        assert_eq!(core.registers().i, 0x300);
+    }
+}
+
+#[test]
+fn store_and_load_registers() {
+    let mut core = core();
+    set_i(&mut core, 0x300);
+    set_v(&mut core, &[(0, 1), (1, 2), (2, 3), (3, 4)]);
+    run(&mut core, 0xf255);
+    assert_eq!(&core.memory()[0x300..0x304], &[1, 2, 3, 0]);
+    assert_eq!(core.registers().i, 0x303);
+
+    core.write_memory(0x303, &[7, 8]);
+    run(&mut core, 0xf165);
+    assert_eq!((v(&core, 0), v(&core, 1), v(&core, 2)), (7, 8, 3));
+    assert_eq!(core.registers().i, 0x305);
+}
+
+#[test]
+fn load_store_index_quirks() {
+    for &(inc, i) in &[(IndexIncrement::Unchanged, 0x300),
+                       (IndexIncrement::ByX, 0x302),
+                       (IndexIncrement::ByXPlusOne, 0x303)] {
+        let quirks = Quirks { load_store_index: inc, ..Quirks::cosmac_vip() };
+        let mut core = core_with(Machine::Chip8, quirks);
+        set_i(&mut core, 0x300);
+        run(&mut core, 0xf255);
+        assert_eq!(core.registers().i, i, "{:?}", inc);
+    }
+}
+
+#[test]
+fn load_through_vf() {
+    let mut core = core();
+    set_i(&mut core, 0x300);
+    core.write_memory(0x30f, &[0xaa]);
+    run(&mut core, 0xff65);
+    assert_eq!(v(&core, 0xf), 0xaa);
+}
+
+#[test]
+fn store_past_end_of_memory_faults() {
+    let mut core = core();
+    set_i(&mut core, 0xffe);
+    assert_eq!(core.execute_opcode(0xf255),
+               Err(CoreError::MemoryOutOfBounds { pc: 0x200, addr: 0x1000 }));
+}
+
+#[test]
+fn rpl_flags() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    set_v(&mut core, &[(0, 1), (1, 2), (2, 3)]);
+    run(&mut core, 0xf175);
+    set_v(&mut core, &[(0, 0), (1, 0), (2, 0)]);
+    run(&mut core, 0xf285);
+    assert_eq!((v(&core, 0), v(&core, 1), v(&core, 2)), (1, 2, 0));
+}
+
+#[test]
+fn register_ranges() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    set_i(&mut core, 0x300);
+    set_v(&mut core, &[(2, 0xa), (3, 0xb), (4, 0xc)]);
+    run(&mut core, 0x5242);
+    assert_eq!(&core.memory()[0x300..0x303], &[0xa, 0xb, 0xc]);
+    assert_eq!(core.registers().i, 0x300);
+
+    // Y below X loads in reverse
+    run(&mut core, 0x5743);
+    assert_eq!((v(&core, 7), v(&core, 6), v(&core, 5), v(&core, 4)), (0xa, 0xb, 0xc, 0));
+}
+
+#[test]
+fn ld_i_long() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    core.write_memory(0x202, &[0xbe, 0xef]);
+    run(&mut core, 0xf000);
+    assert_eq!(core.registers().i, 0xbeef);
+    assert_eq!(pc(&core), 0x204);
+}
+
+#[test]
+fn plane() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    assert_eq!(core.planes(), 1);
+    run(&mut core, 0xf201);
+    assert_eq!(core.planes(), 2);
+}
+
+#[test]
+fn audio_and_pitch() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    assert!(core.audio_pattern().is_none());
+    let pattern: Vec<u8> = (0..16).collect();
+    core.write_memory(0x300, &pattern);
+    set_i(&mut core, 0x300);
+    run(&mut core, 0xf002);
+    let (loaded, rate) = core.audio_pattern().unwrap();
+    assert_eq!(&loaded[..], &pattern[..]);
+    assert_eq!(rate, 4000.0);
+
+    set_v(&mut core, &[(1, 112)]);
+    run(&mut core, 0xf13a);
+    assert_eq!(core.audio_pattern().unwrap().1, 8000.0);
+}
+
+#[test]
+fn scroll_down_and_up() {
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    draw_row(&mut core, 0, 0, 0x80);
+    run(&mut core, 0x00c3);
+    assert_eq!((pixel(&core, 0, 0), pixel(&core, 0, 3)), (0, 1));
+    run(&mut core, 0x00d2);
+    assert_eq!((pixel(&core, 0, 3), pixel(&core, 0, 1)), (0, 1));
+}
+
+#[test]
+fn scroll_left_and_right() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    draw_row(&mut core, 8, 0, 0x80);
+    run(&mut core, 0x00fb);
+    assert_eq!((pixel(&core, 8, 0), pixel(&core, 12, 0)), (0, 1));
+    run(&mut core, 0x00fc);
+    run(&mut core, 0x00fc);
+    assert_eq!((pixel(&core, 12, 0), pixel(&core, 4, 0)), (0, 1));
+
+    // Pixels pushed off the edge are lost
+    draw_row(&mut core, 62, 1, 0xc0);
+    run(&mut core, 0x00fb);
+    assert_eq!(lit(&core), 1);
+}
+
+#[test]
+fn exit() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    run(&mut core, 0x00fd);
+    assert!(core.has_exited());
+}
+
+#[test]
+fn resolution_switches_clear_the_screen() {
+    let mut core = core_with(Machine::SuperChip, Quirks::super_chip());
+    draw_row(&mut core, 0, 0, 0xff);
+    run(&mut core, 0x00ff);
+    assert_eq!(core.resolution(), (128, 64));
+    assert_eq!(lit(&core), 0);
+
+    draw_row(&mut core, 120, 63, 0xff);
+    run(&mut core, 0x00fe);
+    assert_eq!(core.resolution(), (64, 32));
+    assert_eq!(lit(&core), 0);
+}
+
+#[test]
+fn extensions_are_unknown_on_plain_chip8() {
+    let mut core = core();
+    for opcode in &[0x00ff, 0x00fb, 0xf075, 0x5122, 0xf000] {
+        assert_eq!(core.execute_opcode(*opcode),
+                   Err(CoreError::UnknownOpcode { pc: pc(&core) - 2, opcode: *opcode }));
+    }
+}
+
+#[test]
+fn sys_is_unknown() {
+    // Only 00E0 and 00EE are CLS and RET, the rest of 0NNN calls machine code
+    let mut core = core_with(Machine::XoChip, Quirks::octo());
+    for opcode in &[0x0123, 0x01e0, 0x03ee] {
+        assert_eq!(core.execute_opcode(*opcode),
+                   Err(CoreError::UnknownOpcode { pc: pc(&core) - 2, opcode: *opcode }));
+    }
+}
@@ -0,0 +1,89 @@
+//! Save states: what they restore and what they leave alone.
+
+extern crate chip8r;
+
+use chip8r::chip8::{Core, Machine};
+use chip8r::quirks::Quirks;
+use chip8r::rewind::Rewind;
+
+fn core() -> Core {
+    Core::new(Machine::Chip8, Quirks::cosmac_vip())
+}
+
+// Whether EX9E sees the key in V0 as held
+fn key_held(core: &mut Core, key: u8) -> bool {
+    let mut regs = core.registers();
+    regs.v[0] = key;
+    regs.pc = 0x200;
+    core.set_registers(&regs);
+    core.execute_opcode(0xe09e).unwrap();
+    core.registers().pc == 0x204
+}
+
+#[test]
+fn round_trip() {
+    let mut core = core();
+    core.load_rom(&[0x60, 0x2a, 0xa3, 0x45, 0x12, 0x04]).unwrap();
+    core.tick().unwrap();
+    core.tick().unwrap();
+    let state = core.save_state();
+
+    core.tick().unwrap();
+    core.soft_reset();
+    core.load_state(&state).unwrap();
+    let regs = core.registers();
+    assert_eq!((regs.v[0], regs.i, regs.pc), (0x2a, 0x345, 0x204));
+}
+
+#[test]
+fn keys_held_at_save_are_not_restored() {
+    let mut core = core();
+    core.set_key(5);
+    let state = core.save_state();
+
+    core.clear_key(5);
+    core.set_key(7);
+    core.load_state(&state).unwrap();
+    assert!(!key_held(&mut core, 5));
+    assert!(key_held(&mut core, 7));
+}
+
+#[test]
+fn key_wait_restarts_once_its_key_is_released() {
+    let mut core = core();
+    // FX0A saw key 5 go down but not back up
+    core.execute_opcode(0xf30a).unwrap();
+    core.set_key(5);
+    let state = core.save_state();
+
+    core.clear_key(5);
+    core.soft_reset();
+    core.load_state(&state).unwrap();
+    assert!(core.waiting_for_key());
+
+    // Any key now completes it
+    core.set_key(9);
+    core.clear_key(9);
+    assert!(!core.waiting_for_key());
+    assert_eq!(core.registers().v[3], 9);
+}
+
+#[test]
+fn rewind_keeps_live_keypad() {
+    let mut core = core();
+    core.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    let mut rewind = Rewind::new(60, 1 << 20);
+    for frame in 0..10 {
+        if frame == 3 {
+            core.set_key(2);
+        }
+        core.run_frame(4).unwrap();
+        rewind.push(core.save_state());
+    }
+
+    core.clear_key(2);
+    while let Some(state) = rewind.pop() {
+        core.load_state(&state).unwrap();
+        assert!(!key_held(&mut core, 2));
+    }
+}